@@ -0,0 +1,65 @@
+//! Just enough of the AML in the DSDT to find the sleep types.
+
+use super::{table_at, Fadt, SdtHeader};
+
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const BYTE_PREFIX: u8 = 0x0a;
+const ROOT_CHAR: u8 = b'\\';
+
+/// Values to write to the SLP_TYP fields of the PM1a and PM1b control registers
+/// to enter a sleep state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepType {
+    pub a: u8,
+    pub b: u8,
+}
+
+/// Differentiated System Description Table, found through the FADT.
+pub fn dsdt() -> Option<&'static SdtHeader> {
+    let header = unsafe { table_at(Fadt::get()?.dsdt_address()) };
+    (&header.signature == b"DSDT" && header.is_valid()).then_some(header)
+}
+
+/// Sleep type of the state `name`, e.g. `_S5_` for soft off.
+///
+/// Looks for the `Name(_Sx_, Package() {a, b, ...})` object in the DSDT rather
+/// than interpreting the AML, which is how nearly every firmware declares it.
+pub fn sleep_type(name: &[u8; 4]) -> Option<SleepType> {
+    let aml = dsdt()?.data();
+
+    (1..aml.len().saturating_sub(4))
+        .filter(|&offset| &aml[offset..offset + 4] == name)
+        .filter(|&offset| {
+            aml[offset - 1] == NAME_OP
+                || (offset >= 2 && aml[offset - 1] == ROOT_CHAR && aml[offset - 2] == NAME_OP)
+        })
+        .find_map(|offset| parse_package(&aml[offset + 4..]))
+}
+
+/// Reads the first two integers of a package of byte constants.
+fn parse_package(aml: &[u8]) -> Option<SleepType> {
+    let (&op, aml) = aml.split_first()?;
+    if op != PACKAGE_OP {
+        return None;
+    }
+
+    // The top two bits of the PkgLength lead byte count the bytes that follow.
+    let length_bytes = (*aml.first()? >> 6) as usize;
+    let aml = aml.get(1 + length_bytes..)?;
+    // NumElements.
+    let aml = aml.get(1..)?;
+
+    let (a, aml) = parse_byte(aml)?;
+    let (b, _) = parse_byte(aml)?;
+    Some(SleepType { a, b })
+}
+
+/// ZeroOp, OneOp and BytePrefix constants, the ones sleep packages use.
+fn parse_byte(aml: &[u8]) -> Option<(u8, &[u8])> {
+    match aml {
+        [BYTE_PREFIX, value, rest @ ..] => Some((*value, rest)),
+        [value @ (0x00 | 0x01), rest @ ..] => Some((*value, rest)),
+        _ => None,
+    }
+}
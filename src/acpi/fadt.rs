@@ -0,0 +1,139 @@
+use core::mem;
+use x86_64::PhysAddr;
+
+use super::{find_table, GenericAddress, SdtHeader};
+
+/// Fixed ACPI Description Table, as far as ACPI 1.0 defines it.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub header: SdtHeader,
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    pub reserved: u8,
+    pub preferred_pm_profile: u8,
+    /// Interrupt the ACPI events are signaled on.
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub s4bios_request: u8,
+    pub pstate_control: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm2_control_block: u32,
+    pub pm_timer_block: u32,
+    pub gpe0_block: u32,
+    pub gpe1_block: u32,
+    pub pm1_event_length: u8,
+    pub pm1_control_length: u8,
+    pub pm2_control_length: u8,
+    pub pm_timer_length: u8,
+    pub gpe0_length: u8,
+    pub gpe1_length: u8,
+    pub gpe1_base: u8,
+    pub cstate_control: u8,
+    pub worst_c2_latency: u16,
+    pub worst_c3_latency: u16,
+    pub flush_size: u16,
+    pub flush_stride: u16,
+    pub duty_offset: u8,
+    pub duty_width: u8,
+    pub day_alarm: u8,
+    pub month_alarm: u8,
+    /// CMOS register of the century, 0 if there is none.
+    pub century: u8,
+    pub boot_architecture_flags: u16,
+    pub reserved2: u8,
+    pub flags: u32,
+}
+
+/// Fields appended to the FADT by ACPI 2.0.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct FadtExtended {
+    pub reset_register: GenericAddress,
+    pub reset_value: u8,
+    pub arm_boot_architecture_flags: u16,
+    pub minor_version: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_event_block: GenericAddress,
+    pub x_pm1b_event_block: GenericAddress,
+    pub x_pm1a_control_block: GenericAddress,
+    pub x_pm1b_control_block: GenericAddress,
+    pub x_pm2_control_block: GenericAddress,
+    pub x_pm_timer_block: GenericAddress,
+    pub x_gpe0_block: GenericAddress,
+    pub x_gpe1_block: GenericAddress,
+}
+
+impl Fadt {
+    /// The reset register is supported.
+    pub const RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+    pub fn get() -> Option<&'static Fadt> {
+        let header = find_table(b"FACP")?;
+        if (header.length as usize) < mem::size_of::<Fadt>() {
+            return None;
+        }
+
+        Some(unsafe { &*(header as *const SdtHeader as *const Fadt) })
+    }
+
+    /// ACPI 2.0 fields, `None` for a table of ACPI 1.0.
+    pub fn extended(&self) -> Option<&FadtExtended> {
+        let length = mem::size_of::<Fadt>() + mem::size_of::<FadtExtended>();
+        if (self.header.length as usize) < length {
+            return None;
+        }
+
+        Some(unsafe { &*(self as *const Fadt).add(1).cast::<FadtExtended>() })
+    }
+
+    /// Physical address of the DSDT, which holds the AML of the machine.
+    pub fn dsdt_address(&self) -> PhysAddr {
+        let x_dsdt = self.extended().map_or(0, |extended| extended.x_dsdt);
+        PhysAddr::new(if x_dsdt != 0 {
+            x_dsdt
+        } else {
+            self.dsdt as u64
+        })
+    }
+
+    /// I/O port of the PM1a control register, which puts the machine to sleep.
+    pub fn pm1a_control_port(&self) -> Option<u16> {
+        let extended = self
+            .extended()
+            .map(|extended| extended.x_pm1a_control_block);
+        io_port(self.pm1a_control_block, extended)
+    }
+
+    /// Like [`Fadt::pm1a_control_port`] for the optional second block.
+    pub fn pm1b_control_port(&self) -> Option<u16> {
+        let extended = self
+            .extended()
+            .map(|extended| extended.x_pm1b_control_block);
+        io_port(self.pm1b_control_block, extended)
+    }
+
+    /// Register that resets the machine when `value` is written to it.
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        let extended = self.extended()?;
+        (self.flags & Self::RESET_REG_SUPPORTED != 0)
+            .then_some((extended.reset_register, extended.reset_value))
+    }
+}
+
+/// Port of a register block, the 64 bit address takes precedence when it is set.
+fn io_port(legacy: u32, extended: Option<GenericAddress>) -> Option<u16> {
+    match extended.filter(|block| block.address != 0) {
+        Some(block) if block.address_space == GenericAddress::SYSTEM_IO => {
+            Some(block.address as u16)
+        }
+        Some(_) => None,
+        None => (legacy != 0).then_some(legacy as u16),
+    }
+}
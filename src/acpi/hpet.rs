@@ -0,0 +1,33 @@
+use x86_64::PhysAddr;
+
+use super::{find, GenericAddress, SdtHeader};
+
+/// Description of the first HPET of the machine.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct HpetTable {
+    pub header: SdtHeader,
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    /// Main counter ticks below which periodic interrupts are unreliable.
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl HpetTable {
+    pub fn get() -> Option<&'static HpetTable> {
+        find(b"HPET")
+    }
+
+    /// Physical address of the registers, `None` unless they are memory mapped.
+    pub fn address(&self) -> Option<PhysAddr> {
+        let base_address = self.base_address;
+        (base_address.address_space == GenericAddress::SYSTEM_MEMORY)
+            .then(|| PhysAddr::new(base_address.address))
+    }
+
+    pub fn comparators(&self) -> u8 {
+        ((self.event_timer_block_id >> 8) & 0x1f) as u8 + 1
+    }
+}
@@ -0,0 +1,182 @@
+use core::mem;
+
+use super::{find, SdtHeader};
+
+/// Local APIC flag telling that the processor can be used.
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+
+/// Multiple APIC Description Table, lists the interrupt controllers.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    pub header: SdtHeader,
+    pub local_apic_address: u32,
+    pub flags: u32,
+}
+
+impl Madt {
+    /// The machine also has dual 8259s, which must be masked to use the APIC.
+    pub const PCAT_COMPAT: u32 = 1 << 0;
+
+    pub fn get() -> Option<&'static Madt> {
+        find(b"APIC")
+    }
+
+    pub fn entries(&self) -> MadtEntries<'_> {
+        let data = self.header.data();
+        MadtEntries {
+            data: &data[mem::size_of::<Madt>() - mem::size_of::<SdtHeader>()..],
+        }
+    }
+
+    /// APIC IDs of the enabled processors. Online capable ones are left out, they
+    /// are not present until hot-plugged.
+    pub fn processors(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::LocalApic { apic_id, flags, .. } => {
+                usable_processor(flags).then_some(apic_id as u32)
+            }
+            MadtEntry::LocalX2Apic {
+                x2apic_id, flags, ..
+            } => usable_processor(flags).then_some(x2apic_id),
+            _ => None,
+        })
+    }
+}
+
+fn usable_processor(flags: u32) -> bool {
+    flags & LOCAL_APIC_ENABLED != 0
+}
+
+/// Interrupt controller structure of the MADT.
+#[derive(Debug, Clone, Copy)]
+pub enum MadtEntry {
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        flags: u32,
+    },
+    IoApic {
+        id: u8,
+        address: u32,
+        /// First global system interrupt of the I/O APIC.
+        gsi_base: u32,
+    },
+    /// ISA IRQ `source` is wired to `gsi` rather than to the input of the same
+    /// number.
+    InterruptOverride {
+        bus: u8,
+        source: u8,
+        gsi: u32,
+        /// MPS INTI flags, polarity in bits 0-1 and trigger mode in bits 2-3.
+        flags: u16,
+    },
+    NmiSource {
+        flags: u16,
+        gsi: u32,
+    },
+    LocalApicNmi {
+        /// 0xff for all processors.
+        processor_id: u8,
+        flags: u16,
+        lint: u8,
+    },
+    /// 64 bit address of the local APICs, replaces the one in the header.
+    LocalApicOverride {
+        address: u64,
+    },
+    LocalX2Apic {
+        x2apic_id: u32,
+        flags: u32,
+        processor_uid: u32,
+    },
+    Unknown {
+        kind: u8,
+        length: u8,
+    },
+}
+
+/// Iterator over the entries of the MADT, see [`Madt::entries`].
+pub struct MadtEntries<'a> {
+    data: &'a [u8],
+}
+
+impl MadtEntries<'_> {
+    fn u16_at(entry: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([entry[offset], entry[offset + 1]])
+    }
+
+    fn u32_at(entry: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(entry[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(entry: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(entry[offset..offset + 8].try_into().unwrap())
+    }
+}
+
+impl Iterator for MadtEntries<'_> {
+    type Item = MadtEntry;
+
+    fn next(&mut self) -> Option<MadtEntry> {
+        let (kind, length) = match self.data {
+            [kind, length, ..] if *length >= 2 && *length as usize <= self.data.len() => {
+                (*kind, *length)
+            }
+            _ => return None,
+        };
+        let (entry, rest) = self.data.split_at(length as usize);
+        self.data = rest;
+
+        let known_length = match kind {
+            0 => 8,
+            1 => 12,
+            2 => 10,
+            3 => 8,
+            4 => 6,
+            5 => 12,
+            9 => 16,
+            _ => 0,
+        };
+        if (length as usize) < known_length {
+            return Some(MadtEntry::Unknown { kind, length });
+        }
+
+        Some(match kind {
+            0 => MadtEntry::LocalApic {
+                processor_id: entry[2],
+                apic_id: entry[3],
+                flags: Self::u32_at(entry, 4),
+            },
+            1 => MadtEntry::IoApic {
+                id: entry[2],
+                address: Self::u32_at(entry, 4),
+                gsi_base: Self::u32_at(entry, 8),
+            },
+            2 => MadtEntry::InterruptOverride {
+                bus: entry[2],
+                source: entry[3],
+                gsi: Self::u32_at(entry, 4),
+                flags: Self::u16_at(entry, 8),
+            },
+            3 => MadtEntry::NmiSource {
+                flags: Self::u16_at(entry, 2),
+                gsi: Self::u32_at(entry, 4),
+            },
+            4 => MadtEntry::LocalApicNmi {
+                processor_id: entry[2],
+                flags: Self::u16_at(entry, 3),
+                lint: entry[5],
+            },
+            5 => MadtEntry::LocalApicOverride {
+                address: Self::u64_at(entry, 4),
+            },
+            9 => MadtEntry::LocalX2Apic {
+                x2apic_id: Self::u32_at(entry, 4),
+                flags: Self::u32_at(entry, 8),
+                processor_uid: Self::u32_at(entry, 12),
+            },
+            _ => MadtEntry::Unknown { kind, length },
+        })
+    }
+}
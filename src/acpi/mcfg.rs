@@ -0,0 +1,51 @@
+use core::{mem, ptr};
+use x86_64::PhysAddr;
+
+use super::{find, SdtHeader};
+
+/// PCI Express memory mapped configuration space description.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Mcfg {
+    pub header: SdtHeader,
+    pub reserved: u64,
+}
+
+/// Configuration space of the buses `start_bus..=end_bus` of a PCI segment.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    /// Where the configuration space of bus 0 would be, even if `start_bus` is not 0.
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+    pub reserved: u32,
+}
+
+impl Mcfg {
+    pub fn get() -> Option<&'static Mcfg> {
+        find(b"MCFG")
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> + '_ {
+        let entries = &self.header.data()[mem::size_of::<u64>()..];
+        entries
+            .chunks_exact(mem::size_of::<McfgEntry>())
+            .map(|entry| unsafe { ptr::read_unaligned(entry.as_ptr() as *const McfgEntry) })
+    }
+}
+
+impl McfgEntry {
+    pub fn contains(&self, bus: u8) -> bool {
+        (self.start_bus..=self.end_bus).contains(&bus)
+    }
+
+    /// Physical address of the 4 KiB configuration space of a function.
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> PhysAddr {
+        PhysAddr::new(
+            self.base_address
+                + ((bus as u64) << 20 | (device as u64) << 15 | (function as u64) << 12),
+        )
+    }
+}
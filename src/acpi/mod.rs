@@ -0,0 +1,280 @@
+//! ACPI tables handed over by the firmware.
+//!
+//! Tables are read in place through the bootloader's mapping of physical memory,
+//! so [`init`] needs `memory::init` to have run.
+
+pub mod dsdt;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+
+pub use dsdt::{dsdt, sleep_type, SleepType};
+pub use fadt::{Fadt, FadtExtended};
+pub use hpet::HpetTable;
+pub use madt::{Madt, MadtEntry};
+pub use mcfg::{Mcfg, McfgEntry};
+
+use conquer_once::spin::OnceCell;
+use core::{mem, ptr, slice, str};
+use x86_64::PhysAddr;
+
+use crate::{phys_to_virt_addr, serial_println};
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+/// Size of the RSDP of ACPI 1.0, covered by its first checksum.
+const RSDP_V1_LENGTH: usize = 20;
+/// Real mode segment of the extended BIOS data area.
+const EBDA_SEGMENT: u64 = 0x40e;
+const BIOS_AREA_START: u64 = 0xe_0000;
+const BIOS_AREA_END: u64 = 0x10_0000;
+
+static ROOT: OnceCell<RootTable> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// No valid RSDP in the BIOS areas.
+    NoRsdp,
+    /// A table did not sum up to zero.
+    BadChecksum,
+}
+
+/// Root System Description Pointer, the fields of ACPI 2.0 included.
+#[allow(dead_code)]
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// Header shared by all system description tables.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    /// Length of the whole table, header included.
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn signature(&self) -> &str {
+        str::from_utf8(&self.signature).unwrap_or("????")
+    }
+
+    fn is_valid(&self) -> bool {
+        self.length as usize >= mem::size_of::<SdtHeader>()
+            && unsafe { checksum(self as *const _ as *const u8, self.length as usize) }
+    }
+
+    /// Bytes following the header.
+    fn data(&self) -> &[u8] {
+        let header = mem::size_of::<SdtHeader>();
+        unsafe {
+            slice::from_raw_parts(
+                (self as *const _ as *const u8).add(header),
+                self.length as usize - header,
+            )
+        }
+    }
+}
+
+/// Address of a register in one of the ACPI address spaces.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+}
+
+/// RSDT with 32 bit entries or XSDT with 64 bit entries.
+struct RootTable {
+    header: &'static SdtHeader,
+    entry_size: usize,
+}
+
+/// Locates the RSDP and validates the root table it points to.
+pub fn init() -> Result<(), Error> {
+    let rsdp = find_rsdp().ok_or(Error::NoRsdp)?;
+
+    let root = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        RootTable {
+            header: unsafe { table_at(PhysAddr::new(rsdp.xsdt_address)) },
+            entry_size: 8,
+        }
+    } else {
+        RootTable {
+            header: unsafe { table_at(PhysAddr::new(rsdp.rsdt_address as u64)) },
+            entry_size: 4,
+        }
+    };
+    if !root.header.is_valid() {
+        return Err(Error::BadChecksum);
+    }
+
+    ROOT.init_once(|| root);
+    Ok(())
+}
+
+pub fn is_available() -> bool {
+    ROOT.is_initialized()
+}
+
+/// Valid tables listed in the RSDT or XSDT, empty before [`init`].
+pub fn tables() -> impl Iterator<Item = &'static SdtHeader> {
+    let (entries, entry_size) = match ROOT.get() {
+        Some(root) => (root.header.data(), root.entry_size),
+        None => (&[][..], 4),
+    };
+
+    entries
+        .chunks_exact(entry_size)
+        .map(|entry| {
+            let mut address = [0; 8];
+            address[..entry.len()].copy_from_slice(entry);
+            u64::from_le_bytes(address)
+        })
+        .filter(|&address| address != 0)
+        .map(|address| unsafe { table_at(PhysAddr::new(address)) })
+        .filter(|header| header.is_valid())
+}
+
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+    tables().find(|header| &header.signature == signature)
+}
+
+/// Prints every table and the parsed contents of the known ones over serial.
+pub fn dump() {
+    for header in tables() {
+        let (oem_id, oem_table_id) = (header.oem_id, header.oem_table_id);
+        serial_println!(
+            "ACPI {} rev {} length {} OEM {} {}",
+            header.signature(),
+            header.revision,
+            { header.length },
+            str::from_utf8(&oem_id).unwrap_or("?"),
+            str::from_utf8(&oem_table_id).unwrap_or("?"),
+        );
+    }
+
+    if let Some(madt) = Madt::get() {
+        serial_println!(
+            "MADT local APIC {:#x} flags {:#x}",
+            { madt.local_apic_address },
+            { madt.flags }
+        );
+        for entry in madt.entries() {
+            serial_println!("  {:?}", entry);
+        }
+    }
+    if let Some(fadt) = Fadt::get() {
+        serial_println!(
+            "FADT DSDT {:?} SCI {} PM1a {:?} PM1b {:?} century {} reset {:?}",
+            fadt.dsdt_address(),
+            { fadt.sci_interrupt },
+            fadt.pm1a_control_port(),
+            fadt.pm1b_control_port(),
+            fadt.century,
+            fadt.reset_register(),
+        );
+    }
+    if let Some(hpet) = HpetTable::get() {
+        serial_println!(
+            "HPET {:?} comparators {} minimum tick {}",
+            hpet.address(),
+            hpet.comparators(),
+            { hpet.minimum_tick }
+        );
+    }
+    if let Some(mcfg) = Mcfg::get() {
+        for entry in mcfg.entries() {
+            serial_println!(
+                "MCFG segment {} buses {}-{} at {:#x}",
+                { entry.segment },
+                entry.start_bus,
+                entry.end_bus,
+                { entry.base_address }
+            );
+        }
+    }
+}
+
+/// Finds the table with `signature` and views it as a `T`, which starts with
+/// the header and is packed.
+fn find<T>(signature: &[u8; 4]) -> Option<&'static T> {
+    let header = find_table(signature)?;
+    if (header.length as usize) < mem::size_of::<T>() {
+        return None;
+    }
+
+    Some(unsafe { &*(header as *const SdtHeader as *const T) })
+}
+
+/// Whether the `len` bytes at `bytes` sum up to zero.
+unsafe fn checksum(bytes: *const u8, len: usize) -> bool {
+    slice::from_raw_parts(bytes, len)
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+        == 0
+}
+
+unsafe fn table_at(phys: PhysAddr) -> &'static SdtHeader {
+    &*phys_to_virt_addr(phys).as_ptr::<SdtHeader>()
+}
+
+/// Scans the first KiB of the EBDA and then the BIOS ROM area, the RSDP sits on
+/// a 16 byte boundary in one of them.
+fn find_rsdp() -> Option<Rsdp> {
+    let segment = phys_to_virt_addr(PhysAddr::new(EBDA_SEGMENT)).as_ptr::<u16>();
+    let ebda = unsafe { ptr::read_unaligned(segment) } as u64 * 16;
+
+    Some(ebda..ebda + 1024)
+        .filter(|area| area.start != 0)
+        .into_iter()
+        .chain(Some(BIOS_AREA_START..BIOS_AREA_END))
+        .flat_map(|area| area.step_by(16))
+        .find_map(|address| unsafe { rsdp_at(PhysAddr::new(address)) })
+}
+
+unsafe fn rsdp_at(phys: PhysAddr) -> Option<Rsdp> {
+    let bytes = phys_to_virt_addr(phys).as_ptr::<u8>();
+    if slice::from_raw_parts(bytes, RSDP_SIGNATURE.len()) != RSDP_SIGNATURE
+        || !checksum(bytes, RSDP_V1_LENGTH)
+    {
+        return None;
+    }
+
+    let mut rsdp: Rsdp = mem::zeroed();
+    ptr::copy_nonoverlapping(bytes, &mut rsdp as *mut Rsdp as *mut u8, RSDP_V1_LENGTH);
+    if rsdp.revision >= 2 {
+        let length = ptr::read_unaligned(bytes.add(RSDP_V1_LENGTH) as *const u32) as usize;
+        if length < mem::size_of::<Rsdp>() || !checksum(bytes, length) {
+            return None;
+        }
+        rsdp = ptr::read_unaligned(bytes as *const Rsdp);
+    }
+
+    Some(rsdp)
+}
@@ -0,0 +1,277 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+};
+
+use super::{
+    stats::{self, HeapStats, Inspect},
+    GrowHeap, GrowableHeap, Locked,
+};
+
+/// Number of free lists, the list at index `order` holds blocks of `1 << order` bytes.
+const ORDERS: usize = 48;
+/// Smallest block handed out, large enough to hold a `FreeBlock`.
+const MIN_BLOCK_SIZE: usize = 16;
+
+/// Free block, always aligned to its own size.
+struct FreeBlock {
+    next: Option<&'static mut FreeBlock>,
+}
+
+impl FreeBlock {
+    fn addr(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+/// Binary buddy allocator.
+///
+/// Every block is a power of two in size and aligned to its size, so the buddy
+/// of a block is found by flipping the bit of its size in the address. Freed
+/// blocks are merged with their buddy for as long as the buddy is free too.
+pub struct BuddyAllocator {
+    free_lists: [Option<&'static mut FreeBlock>; ORDERS],
+    heap_top: usize,
+    heap_size: usize,
+    allocated: usize,
+    /// Used by the `GlobalAlloc` implementation once the heap is exhausted.
+    grow: Option<GrowHeap>,
+}
+
+impl BuddyAllocator {
+    pub const fn new() -> Self {
+        Self::build(None)
+    }
+
+    /// Creates an allocator that grows its heap through `grow` when used as a
+    /// `GlobalAlloc`.
+    pub const fn with_growth(grow: GrowHeap) -> Self {
+        Self::build(Some(grow))
+    }
+
+    const fn build(grow: Option<GrowHeap>) -> Self {
+        const EMPTY: Option<&'static mut FreeBlock> = None;
+        BuddyAllocator {
+            free_lists: [EMPTY; ORDERS],
+            heap_top: 0,
+            heap_size: 0,
+            allocated: 0,
+            grow,
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.add_region(heap_start, heap_size);
+    }
+
+    /// Hands `start..start + size` to the allocator, the region does not need to be
+    /// adjacent to the ones added before.
+    pub unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let end = start + size;
+        let start = super::align_up(start, MIN_BLOCK_SIZE);
+        let end = end - end % MIN_BLOCK_SIZE;
+
+        if start >= end {
+            return;
+        }
+
+        self.insert_range(start, end);
+        self.heap_size += end - start;
+        self.heap_top = self.heap_top.max(end);
+    }
+
+    /// Whether part of `start..start + size` is in a free block.
+    pub fn overlaps_free(&self, start: usize, size: usize) -> bool {
+        self.free_lists.iter().enumerate().any(|(order, head)| {
+            let mut block = head.as_deref();
+            while let Some(current) = block {
+                if current.addr() < start + size && start < current.addr() + (1 << order) {
+                    return true;
+                }
+                block = current.next.as_deref();
+            }
+            false
+        })
+    }
+
+    /// Bytes in blocks currently handed out.
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let order = order_for(layout);
+
+        match self.allocate_order(order) {
+            Some(addr) => {
+                self.allocated += 1 << order;
+                addr as *mut u8
+            }
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let order = order_for(layout);
+
+        self.allocated -= 1 << order;
+        self.free_order(ptr as usize, order);
+    }
+
+    /// Returns part of an allocated block, which may have been obtained with a larger
+    /// layout, without going through the layout it was allocated with.
+    pub unsafe fn release(&mut self, start: usize, size: usize) {
+        self.allocated -= size;
+        self.insert_range(start, start + size);
+    }
+
+    /// Frees `start..end` as the largest naturally aligned blocks that fit.
+    unsafe fn insert_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let align_order = start.trailing_zeros() as usize;
+            let size_order = (usize::BITS - 1 - (end - start).leading_zeros()) as usize;
+            let order = align_order.min(size_order).min(ORDERS - 1);
+
+            self.free_order(start, order);
+            start += 1 << order;
+        }
+    }
+
+    fn allocate_order(&mut self, order: usize) -> Option<usize> {
+        let mut current = (order..ORDERS).find(|&o| self.free_lists[o].is_some())?;
+        let block = self.pop(current)?;
+
+        while current > order {
+            current -= 1;
+            unsafe { self.push(current, block + (1 << current)) };
+        }
+
+        Some(block)
+    }
+
+    unsafe fn free_order(&mut self, mut addr: usize, mut order: usize) {
+        while order + 1 < ORDERS {
+            let buddy = addr ^ (1 << order);
+            if !self.remove(order, buddy) {
+                break;
+            }
+            addr = addr.min(buddy);
+            order += 1;
+        }
+
+        self.push(order, addr);
+    }
+
+    unsafe fn push(&mut self, order: usize, addr: usize) {
+        let block = FreeBlock {
+            next: self.free_lists[order].take(),
+        };
+        let block_ptr = addr as *mut FreeBlock;
+        block_ptr.write(block);
+        self.free_lists[order] = Some(&mut *block_ptr);
+    }
+
+    fn pop(&mut self, order: usize) -> Option<usize> {
+        let block = self.free_lists[order].take()?;
+        self.free_lists[order] = block.next.take();
+        Some(block.addr())
+    }
+
+    /// Removes the free block at `addr` from the list of `order`, if it is there.
+    fn remove(&mut self, order: usize, addr: usize) -> bool {
+        let mut current = &mut self.free_lists[order];
+
+        while current.is_some() {
+            if current.as_ref().unwrap().addr() == addr {
+                let block = current.take().unwrap();
+                *current = block.next.take();
+                return true;
+            }
+            current = &mut current.as_mut().unwrap().next;
+        }
+
+        false
+    }
+}
+
+/// Order of the smallest block satisfying both size and alignment of `layout`.
+fn order_for(layout: Layout) -> usize {
+    let size = layout
+        .size()
+        .max(layout.align())
+        .max(MIN_BLOCK_SIZE)
+        .max(mem::size_of::<FreeBlock>())
+        .next_power_of_two();
+
+    size.trailing_zeros() as usize
+}
+
+impl GrowableHeap for BuddyAllocator {
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        BuddyAllocator::init(self, heap_start, heap_size)
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        BuddyAllocator::allocate(self, layout)
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        BuddyAllocator::deallocate(self, ptr, layout)
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        self.add_region(self.heap_top, by)
+    }
+
+    fn top(&self) -> usize {
+        self.heap_top
+    }
+
+    fn size(&self) -> usize {
+        self.heap_size
+    }
+
+    /// Twice the block size, so that the new memory contains an aligned block.
+    fn growth_for(&self, layout: Layout) -> usize {
+        2 << order_for(layout)
+    }
+}
+
+impl Inspect for BuddyAllocator {
+    fn inspect(&self, stats: &mut HeapStats) {
+        stats.heap_size = self.heap_size;
+
+        for (order, head) in self.free_lists.iter().enumerate() {
+            let mut block = head.as_deref();
+            while let Some(current) = block {
+                stats.free_regions += 1;
+                stats.free_bytes += 1 << order;
+                stats.largest_free_region = stats.largest_free_region.max(1 << order);
+                block = current.next.as_deref();
+            }
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<BuddyAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        let grow = allocator.grow;
+        let ptr = allocator.allocate_or_grow(layout, grow);
+        drop(allocator);
+
+        if !ptr.is_null() {
+            stats::record_alloc(layout);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        stats::record_dealloc(layout);
+        self.lock().deallocate(ptr, layout);
+    }
+}
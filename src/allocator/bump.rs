@@ -1,4 +1,8 @@
-use super::{align_up, Locked};
+use super::{
+    align_up,
+    stats::{self, HeapStats, Inspect},
+    Locked,
+};
 use core::alloc::{GlobalAlloc, Layout};
 
 pub struct BumpAllocator {
@@ -25,6 +29,15 @@ impl BumpAllocator {
     }
 }
 
+impl Inspect for BumpAllocator {
+    fn inspect(&self, stats: &mut HeapStats) {
+        stats.heap_size = self.heap_end - self.heap_start;
+        stats.free_regions = 1;
+        stats.free_bytes = self.heap_end - self.next;
+        stats.largest_free_region = stats.free_bytes;
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut bump = self.lock();
@@ -41,11 +54,13 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
 
         bump.next = alloc_end;
         bump.allocations += 1;
+        stats::record_alloc(layout);
         alloc_start as *mut u8
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: Layout) {
         let mut bump = self.lock();
+        stats::record_dealloc(layout);
 
         bump.allocations -= 1;
 
@@ -0,0 +1,227 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem,
+    ops::Deref,
+    ptr,
+};
+use spin::Mutex;
+
+use super::{align_up, stats, Locked};
+use crate::serial_println;
+
+/// Guard bytes on each side of an allocation.
+const RED_ZONE: usize = 16;
+const RED_ZONE_BYTE: u8 = 0xfd;
+/// Fills memory handed out, so reads of uninitialized memory stand out.
+const ALLOC_POISON: u8 = 0xcd;
+/// Fills freed memory, any other value found there later was written after the free.
+const FREE_POISON: u8 = 0xdd;
+
+const LIVE_MAGIC: u64 = 0xa110_ca7e_d0b1_ec75;
+const FREED_MAGIC: u64 = 0xf7ee_d0b1_ec75_dead;
+
+/// Number of freed blocks kept back from the inner allocator to catch late writes.
+const QUARANTINE_LEN: usize = 32;
+/// Larger blocks are poisoned but released right away, checking them on every
+/// allocation would be too slow.
+const QUARANTINE_MAX_SIZE: usize = 4096;
+
+/// Stored right before the front red zone.
+///
+/// The inner allocators keep their free list links in the first two words of a
+/// free block, so `magic` comes last and the freed marker is still there after
+/// the block left the quarantine.
+#[repr(C)]
+struct Header {
+    size: usize,
+    align: usize,
+    /// Distance from the start of the inner allocation to the user pointer.
+    offset: usize,
+    magic: u64,
+}
+
+const HEADER_SIZE: usize = mem::size_of::<Header>();
+
+impl Header {
+    /// The header sits at a fixed distance from the user pointer, so it can be
+    /// found even when the caller passes the wrong layout.
+    unsafe fn of(ptr: *mut u8) -> *mut Header {
+        ptr.sub(RED_ZONE + HEADER_SIZE) as *mut Header
+    }
+
+    fn layout(&self) -> Layout {
+        unsafe { Layout::from_size_align_unchecked(self.size, self.align) }
+    }
+
+    fn inner_layout(&self) -> Layout {
+        inner_layout(self.offset, self.layout())
+    }
+}
+
+fn user_offset(layout: Layout) -> usize {
+    align_up(HEADER_SIZE + RED_ZONE, layout.align().max(RED_ZONE))
+}
+
+fn inner_layout(offset: usize, layout: Layout) -> Layout {
+    Layout::from_size_align(
+        offset + layout.size() + RED_ZONE,
+        layout.align().max(RED_ZONE),
+    )
+    .unwrap()
+}
+
+struct Quarantine {
+    blocks: [usize; QUARANTINE_LEN],
+    next: usize,
+}
+
+/// Wraps the global allocator when the `heap-debug` feature is enabled.
+///
+/// Every allocation is surrounded by red zones and a header recording its layout.
+/// Frees are checked against that header, freed memory is poisoned and kept in a
+/// quarantine for a while, and each allocation verifies the poison of the blocks
+/// in quarantine. Any violation is reported on serial before panicking.
+///
+/// The heap statistics count the inner allocations and treat blocks in quarantine
+/// as freed.
+pub struct DebugAllocator<A> {
+    inner: Locked<A>,
+    quarantine: Mutex<Quarantine>,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        DebugAllocator {
+            inner: Locked::new(inner),
+            quarantine: Mutex::new(Quarantine {
+                blocks: [0; QUARANTINE_LEN],
+                next: 0,
+            }),
+        }
+    }
+}
+
+impl<A> Deref for DebugAllocator<A> {
+    type Target = Locked<A>;
+
+    fn deref(&self) -> &Locked<A> {
+        &self.inner
+    }
+}
+
+fn report(error: &str, ptr: *mut u8, layout: Layout) -> ! {
+    serial_println!(
+        "heap-debug: {} at {:p} (size {:#x}, align {:#x})",
+        error,
+        ptr,
+        layout.size(),
+        layout.align()
+    );
+    panic!("heap-debug: {} at {:p}", error, ptr)
+}
+
+unsafe fn is_filled(start: *mut u8, len: usize, byte: u8) -> bool {
+    (0..len).all(|offset| start.add(offset).read() == byte)
+}
+
+/// Checks that a quarantined block was not written to since it was freed.
+unsafe fn check_poison(ptr: *mut u8) {
+    let header = &*Header::of(ptr);
+    if header.magic != FREED_MAGIC || !is_filled(ptr, header.size, FREE_POISON) {
+        report("use after free", ptr, header.layout());
+    }
+}
+
+unsafe fn check_red_zones(ptr: *mut u8, header: &Header) {
+    if !is_filled(ptr.sub(RED_ZONE), RED_ZONE, RED_ZONE_BYTE) {
+        report("buffer underflow", ptr, header.layout());
+    }
+    if !is_filled(ptr.add(header.size), RED_ZONE, RED_ZONE_BYTE) {
+        report("buffer overflow", ptr, header.layout());
+    }
+}
+
+impl<A> DebugAllocator<A>
+where
+    Locked<A>: GlobalAlloc,
+{
+    /// Hands a block back to the inner allocator, which records the free again.
+    unsafe fn release(&self, ptr: *mut u8) {
+        let header = &*Header::of(ptr);
+        stats::unrecord_dealloc(header.inner_layout());
+        self.inner
+            .dealloc(ptr.sub(header.offset), header.inner_layout());
+    }
+}
+
+unsafe impl<A> GlobalAlloc for DebugAllocator<A>
+where
+    Locked<A>: GlobalAlloc,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        for &block in self.quarantine.lock().blocks.iter() {
+            if block != 0 {
+                check_poison(block as *mut u8);
+            }
+        }
+
+        let offset = user_offset(layout);
+        let start = self.inner.alloc(inner_layout(offset, layout));
+        if start.is_null() {
+            return start;
+        }
+
+        let ptr = start.add(offset);
+        Header::of(ptr).write(Header {
+            size: layout.size(),
+            align: layout.align(),
+            offset,
+            magic: LIVE_MAGIC,
+        });
+        ptr::write_bytes(ptr.sub(RED_ZONE), RED_ZONE_BYTE, RED_ZONE);
+        ptr::write_bytes(ptr, ALLOC_POISON, layout.size());
+        ptr::write_bytes(ptr.add(layout.size()), RED_ZONE_BYTE, RED_ZONE);
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let header = &mut *Header::of(ptr);
+
+        match header.magic {
+            LIVE_MAGIC => {}
+            FREED_MAGIC => report("double free", ptr, layout),
+            _ => report("free of unknown pointer", ptr, layout),
+        }
+        if header.layout() != layout {
+            serial_println!(
+                "heap-debug: allocated with size {:#x}, align {:#x}",
+                header.size,
+                header.align
+            );
+            report("free with wrong layout", ptr, layout);
+        }
+        check_red_zones(ptr, header);
+
+        header.magic = FREED_MAGIC;
+        ptr::write_bytes(ptr, FREE_POISON, header.size);
+        stats::record_dealloc(header.inner_layout());
+
+        if header.size > QUARANTINE_MAX_SIZE {
+            self.release(ptr);
+            return;
+        }
+
+        let evicted = {
+            let mut quarantine = self.quarantine.lock();
+            let index = quarantine.next;
+            quarantine.next = (index + 1) % QUARANTINE_LEN;
+            mem::replace(&mut quarantine.blocks[index], ptr as usize)
+        };
+
+        if evicted != 0 {
+            check_poison(evicted as *mut u8);
+            self.release(evicted as *mut u8);
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use core::{alloc::{GlobalAlloc, Layout}, ptr::NonNull};
+use core::alloc::{GlobalAlloc, Layout};
 
-use super::Locked;
+use super::{
+    stats::{self, HeapStats, Inspect},
+    GrowHeap, GrowableHeap, Locked,
+};
 
 /// The block sizes to use.
 /// They must also  be used as alignment as alignment is always a power of 2
-const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 /// Memory block
 struct ListNode {
@@ -12,25 +15,45 @@ struct ListNode {
 }
 
 ///Allocator
-pub struct FixedSizeBlockAllocator {
+///
+/// Sizes without a block list are served by the fallback heap `F`, which also
+/// grows on demand when the allocator was given a way to grow it.
+pub struct FixedSizeBlockAllocator<F = linked_list_allocator::Heap> {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
-    fallback_allocator: linked_list_allocator::Heap,
+    fallback_allocator: F,
+    grow: Option<GrowHeap>,
 }
 
 impl FixedSizeBlockAllocator {
     pub const fn new() -> Self {
+        Self::with_fallback(linked_list_allocator::Heap::empty())
+    }
+}
+
+impl<F> FixedSizeBlockAllocator<F> {
+    pub const fn with_fallback(fallback_allocator: F) -> Self {
+        Self::build(fallback_allocator, None)
+    }
+
+    /// Like [`with_fallback`](Self::with_fallback), growing the fallback heap
+    /// through `grow` once it is exhausted.
+    pub const fn with_growth(fallback_allocator: F, grow: GrowHeap) -> Self {
+        Self::build(fallback_allocator, Some(grow))
+    }
+
+    const fn build(fallback_allocator: F, grow: Option<GrowHeap>) -> Self {
         const EMPTY: Option<&'static mut ListNode> = None;
         Self {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
-            fallback_allocator: linked_list_allocator::Heap::empty(),
+            fallback_allocator,
+            grow,
         }
     }
+}
 
+impl<F: GrowableHeap> FixedSizeBlockAllocator<F> {
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
-            Err(_) => core::ptr::null_mut(),
-        }
+        self.fallback_allocator.allocate_or_grow(layout, self.grow)
     }
 
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
@@ -38,17 +61,31 @@ impl FixedSizeBlockAllocator {
     }
 }
 
-fn list_index(layout: Layout) -> Option<usize> {
+impl<F: GrowableHeap> Inspect for FixedSizeBlockAllocator<F> {
+    fn inspect(&self, stats: &mut HeapStats) {
+        stats.heap_size = self.fallback_allocator.size();
+
+        for (count, head) in stats.free_blocks.iter_mut().zip(self.list_heads.iter()) {
+            let mut node = head.as_deref();
+            while let Some(current) = node {
+                *count += 1;
+                node = current.next.as_deref();
+            }
+        }
+    }
+}
+
+pub(super) fn list_index(layout: Layout) -> Option<usize> {
     let required_size = layout.size().max(layout.align());
 
     BLOCK_SIZES.iter().position(|&s| s >= required_size)
 }
 
-unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+unsafe impl<F: GrowableHeap> GlobalAlloc for Locked<FixedSizeBlockAllocator<F>> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
 
-        match list_index(layout) {
+        let ptr = match list_index(layout) {
             Some(index) => match allocator.list_heads[index].take() {
                 Some(node) => {
                     allocator.list_heads[index] = node.next.take();
@@ -64,11 +101,17 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 }
             },
             None => allocator.fallback_alloc(layout),
+        };
+
+        if !ptr.is_null() {
+            stats::record_alloc(layout);
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        stats::record_dealloc(layout);
 
         match list_index(layout) {
             Some(index) => {
@@ -84,10 +127,7 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 new_node_ptr.write(new_node);
                 allocator.list_heads[index] = Some(&mut *new_node_ptr);
             }
-            None =>{
-                let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout)
-            }
+            None => allocator.fallback_allocator.deallocate(ptr, layout),
         }
     }
 }
@@ -1,4 +1,8 @@
-use super::{align_up, Locked};
+use super::{
+    align_up,
+    stats::{self, HeapStats, Inspect},
+    Locked,
+};
 use core::{
     alloc::{GlobalAlloc, Layout},
     mem,
@@ -25,12 +29,14 @@ impl ListNode {
 
 pub struct LinkedListAllocator {
     head: ListNode,
+    heap_size: usize,
 }
 
 impl LinkedListAllocator {
     pub const fn new() -> Self {
         LinkedListAllocator {
             head: ListNode::new(0),
+            heap_size: 0,
         }
     }
 
@@ -76,6 +82,7 @@ impl LinkedListAllocator {
     }
 
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_size = heap_size;
         self.add_free_region(heap_start, heap_size);
     }
 
@@ -91,6 +98,20 @@ impl LinkedListAllocator {
     }
 }
 
+impl Inspect for LinkedListAllocator {
+    fn inspect(&self, stats: &mut HeapStats) {
+        stats.heap_size = self.heap_size;
+
+        let mut region = self.head.next.as_deref();
+        while let Some(current) = region {
+            stats.free_regions += 1;
+            stats.free_bytes += current.size;
+            stats.largest_free_region = stats.largest_free_region.max(current.size);
+            region = current.next.as_deref();
+        }
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let (size, align) = LinkedListAllocator::size_align(layout);
@@ -102,6 +123,7 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
             if excess_size > 0 {
                 allocator.add_free_region(alloc_end, excess_size);
             }
+            stats::record_alloc(layout);
             alloc_start as *mut u8
         } else {
             core::ptr::null_mut()
@@ -110,6 +132,7 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let (size, _) = LinkedListAllocator::size_align(layout);
+        stats::record_dealloc(layout);
         self.lock().add_free_region(ptr as usize, size);
     }
 }
@@ -1,7 +1,17 @@
+pub mod buddy;
 pub mod bump;
+#[cfg(feature = "heap-debug")]
+pub mod debug;
 pub mod fixed_size;
 pub mod linked_list;
+pub mod slab;
+pub mod stats;
 
+use core::{
+    alloc::Layout,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use spin::Mutex;
 use x86_64::{
     structures::paging::{
@@ -10,28 +20,57 @@ use x86_64::{
     VirtAddr,
 };
 
-#[cfg(bump_allocator)]
-use bump::BumpAllocator;
-
-use crate::memory::{BootInfoFrameAllocator, FRAME_ALLOCATOR};
+use crate::{
+    memory::{FRAME_ALLOCATOR, MAPPER},
+    serial_println,
+};
 
-use fixed_size::FixedSizeBlockAllocator;
+use stats::HeapStats;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024;
+/// Default ceiling for on demand heap growth.
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+/// Minimum number of bytes the heap grows by once it is exhausted.
+const HEAP_GROWTH: usize = 64 * 1024;
+const PAGE_SIZE: usize = 4096;
+
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_MAX_SIZE);
+
+pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
+    map_heap(HEAP_START, HEAP_SIZE)?;
+
+    unsafe {
+        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+
+    Ok(())
+}
+
+/// Sets the size the heap may grow to, rounded up to whole pages and never below `HEAP_SIZE`.
+pub fn set_heap_limit(limit: usize) {
+    HEAP_LIMIT.store(align_up(limit.max(HEAP_SIZE), PAGE_SIZE), Ordering::Relaxed);
+}
+
+pub fn heap_limit() -> usize {
+    HEAP_LIMIT.load(Ordering::Relaxed)
+}
 
-pub fn init_heap(
-    mapper: &mut impl Mapper<Size4KiB>,
-    mut frame_allocator: BootInfoFrameAllocator,
-) -> Result<(), MapToError<Size4KiB>> {
+/// Backs `start..start + size` with freshly allocated frames.
+///
+/// Called with the allocator locked when the heap grows, so it must not allocate.
+fn map_heap(start: usize, size: usize) -> Result<(), MapToError<Size4KiB>> {
     let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_start = VirtAddr::new(start as u64);
+        let heap_end = heap_start + size - 1u64;
         let heap_start_page = Page::containing_address(heap_start);
         let heap_end_page = Page::containing_address(heap_end);
         Page::range_inclusive(heap_start_page, heap_end_page)
     };
 
+    let mut mapper = MAPPER.get().unwrap().lock();
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+
     for page in page_range {
         let frame = frame_allocator
             .allocate_frame()
@@ -39,18 +78,111 @@ pub fn init_heap(
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
         unsafe {
             mapper
-                .map_to(page, frame, flags, &mut frame_allocator)?
+                .map_to(page, frame, flags, &mut *frame_allocator)?
                 .flush()
         }
     }
 
-    FRAME_ALLOCATOR.init_once(|| Mutex::new(frame_allocator));
+    Ok(())
+}
 
-    unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+/// Maps memory right above `top` for a heap that needs at least `required` more
+/// bytes, returns how many bytes it mapped or 0 when the heap cannot grow.
+pub type GrowHeap = fn(top: usize, required: usize) -> usize;
+
+/// Grows the global heap, up to [`heap_limit`] above `HEAP_START`.
+fn grow_heap(top: usize, required: usize) -> usize {
+    let remaining = (HEAP_START + heap_limit()).saturating_sub(top);
+    let size = align_up(required.max(HEAP_GROWTH), PAGE_SIZE).min(remaining);
+
+    if top < HEAP_START || size < required || map_heap(top, size).is_err() {
+        return 0;
     }
+    size
+}
 
-    Ok(())
+/// A heap serving variable sized allocations that can be extended at its top.
+///
+/// Used as the fallback of [`FixedSizeBlockAllocator`](fixed_size::FixedSizeBlockAllocator).
+pub trait GrowableHeap {
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize);
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8;
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Hands the `by` bytes directly above [`top`](GrowableHeap::top) to the heap.
+    unsafe fn extend(&mut self, by: usize);
+
+    fn top(&self) -> usize;
+
+    fn size(&self) -> usize;
+
+    /// Number of bytes the heap must grow by so that `layout` is guaranteed to fit.
+    fn growth_for(&self, layout: Layout) -> usize {
+        layout.size() + layout.align()
+    }
+
+    /// Allocates, first growing the heap through `grow` when it is exhausted.
+    /// Without `grow` the heap keeps the size it has.
+    fn allocate_or_grow(&mut self, layout: Layout, grow: Option<GrowHeap>) -> *mut u8 {
+        let ptr = self.allocate(layout);
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        let size = match grow {
+            Some(grow) => grow(self.top(), self.growth_for(layout)),
+            None => 0,
+        };
+        if size == 0 {
+            return core::ptr::null_mut();
+        }
+
+        unsafe { self.extend(size) };
+        self.allocate(layout)
+    }
+}
+
+impl GrowableHeap for linked_list_allocator::Heap {
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        linked_list_allocator::Heap::init(self, heap_start, heap_size)
+    }
+
+    fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        match self.allocate_first_fit(layout) {
+            Ok(ptr) => ptr.as_ptr(),
+            Err(_) => core::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let ptr = NonNull::new(ptr).unwrap();
+        linked_list_allocator::Heap::deallocate(self, ptr, layout)
+    }
+
+    unsafe fn extend(&mut self, by: usize) {
+        linked_list_allocator::Heap::extend(self, by)
+    }
+
+    fn top(&self) -> usize {
+        linked_list_allocator::Heap::top(self)
+    }
+
+    fn size(&self) -> usize {
+        linked_list_allocator::Heap::size(self)
+    }
+}
+
+/// Collects the allocation counters together with the layout of the active allocator.
+pub fn stats() -> HeapStats {
+    stats::collect(&*ALLOCATOR.lock())
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    serial_println!("{}", stats());
+    panic!("Allocation error: {:?}", layout)
 }
 
 pub struct Locked<T>(Mutex<T>);
@@ -74,9 +206,59 @@ fn align_up(addr: usize, align: usize) -> usize {
     addr - rem + align
 }
 
-#[cfg(bump_allocator)]
+#[cfg(not(any(
+    feature = "alloc-bump",
+    feature = "alloc-linked-list",
+    feature = "alloc-fixed-size",
+    feature = "alloc-buddy"
+)))]
+compile_error!("One of the alloc-* features must be enabled to select the global allocator");
+
+#[cfg(any(
+    all(
+        feature = "alloc-bump",
+        any(
+            feature = "alloc-linked-list",
+            feature = "alloc-fixed-size",
+            feature = "alloc-buddy"
+        )
+    ),
+    all(
+        feature = "alloc-linked-list",
+        any(feature = "alloc-fixed-size", feature = "alloc-buddy")
+    ),
+    all(feature = "alloc-fixed-size", feature = "alloc-buddy")
+))]
+compile_error!("Only one of the alloc-* features can be enabled, use --no-default-features");
+
+#[cfg(feature = "alloc-bump")]
+type HeapAllocator = bump::BumpAllocator;
+#[cfg(feature = "alloc-bump")]
+const HEAP_ALLOCATOR: HeapAllocator = bump::BumpAllocator::new();
+
+#[cfg(feature = "alloc-linked-list")]
+type HeapAllocator = linked_list::LinkedListAllocator;
+#[cfg(feature = "alloc-linked-list")]
+const HEAP_ALLOCATOR: HeapAllocator = linked_list::LinkedListAllocator::new();
+
+// Only the global allocator grows, into the window above `HEAP_START`.
+#[cfg(feature = "alloc-fixed-size")]
+type HeapAllocator = fixed_size::FixedSizeBlockAllocator;
+#[cfg(feature = "alloc-fixed-size")]
+const HEAP_ALLOCATOR: HeapAllocator = fixed_size::FixedSizeBlockAllocator::with_growth(
+    linked_list_allocator::Heap::empty(),
+    grow_heap,
+);
+
+#[cfg(feature = "alloc-buddy")]
+type HeapAllocator = buddy::BuddyAllocator;
+#[cfg(feature = "alloc-buddy")]
+const HEAP_ALLOCATOR: HeapAllocator = buddy::BuddyAllocator::with_growth(grow_heap);
+
+#[cfg(not(feature = "heap-debug"))]
 #[global_allocator]
-static ALLOCATOR: Locked<BumpAllocator> = Locked::new(BumpAllocator::new());
+static ALLOCATOR: Locked<HeapAllocator> = Locked::new(HEAP_ALLOCATOR);
 
+#[cfg(feature = "heap-debug")]
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+static ALLOCATOR: debug::DebugAllocator<HeapAllocator> = debug::DebugAllocator::new(HEAP_ALLOCATOR);
@@ -0,0 +1,331 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    alloc::Layout,
+    fmt,
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+use spin::Mutex;
+use x86_64::{structures::paging::PhysFrame, PhysAddr, VirtAddr};
+
+use super::{align_up, Locked};
+use crate::memory::{self, FRAME_ALLOCATOR, FRAME_SIZE};
+
+/// Slabs are sized to hold at least this many objects.
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+static CACHES: Mutex<Vec<&'static Locked<RawCache>>> = Mutex::new(Vec::new());
+
+/// Lists every cache created with [`SlabCache::create`].
+pub fn caches() -> Vec<SlabStats> {
+    CACHES
+        .lock()
+        .iter()
+        .map(|cache| cache.lock().stats())
+        .collect()
+}
+
+/// Returns the empty slabs of every cache to the frame allocator, returns the
+/// number of slabs released.
+pub fn shrink_all() -> usize {
+    CACHES
+        .lock()
+        .iter()
+        .map(|cache| cache.lock().shrink())
+        .sum()
+}
+
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+/// Lives at the start of every slab, followed by the objects.
+struct SlabHeader {
+    next: Option<NonNull<SlabHeader>>,
+    free: Option<NonNull<FreeObject>>,
+    in_use: usize,
+}
+
+/// Untyped part of a cache, slabs are physically contiguous frames accessed through
+/// the physical memory mapping and aligned to their size.
+struct RawCache {
+    name: &'static str,
+    object_size: usize,
+    first_object: usize,
+    objects_per_slab: usize,
+    slab_frames: usize,
+    slabs: Option<NonNull<SlabHeader>>,
+    slab_count: usize,
+    active_objects: usize,
+}
+
+unsafe impl Send for RawCache {}
+
+impl RawCache {
+    fn new(name: &'static str, layout: Layout) -> Self {
+        let object_align = layout.align().max(mem::align_of::<FreeObject>());
+        let object_size = align_up(
+            layout.size().max(mem::size_of::<FreeObject>()),
+            object_align,
+        );
+        let first_object = align_up(mem::size_of::<SlabHeader>(), object_align);
+
+        let frame_size = FRAME_SIZE as usize;
+        let min_size = first_object + object_size * MIN_OBJECTS_PER_SLAB;
+        let slab_frames = ((min_size + frame_size - 1) / frame_size).next_power_of_two();
+
+        RawCache {
+            name,
+            object_size,
+            first_object,
+            objects_per_slab: (slab_frames * frame_size - first_object) / object_size,
+            slab_frames,
+            slabs: None,
+            slab_count: 0,
+            active_objects: 0,
+        }
+    }
+
+    fn slab_size(&self) -> usize {
+        self.slab_frames * FRAME_SIZE as usize
+    }
+
+    fn allocate(&mut self) -> Result<NonNull<u8>, memory::Error> {
+        let slab = match self.find_partial() {
+            Some(slab) => slab,
+            None => self.grow()?,
+        };
+
+        let header = unsafe { &mut *slab.as_ptr() };
+        let object = header.free.unwrap();
+        header.free = unsafe { object.as_ref().next };
+        header.in_use += 1;
+        self.active_objects += 1;
+
+        Ok(object.cast())
+    }
+
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>) {
+        let header = &mut *self.slab_of(ptr).as_ptr();
+        let object = ptr.cast::<FreeObject>();
+
+        object.as_ptr().write(FreeObject { next: header.free });
+        header.free = Some(object);
+        header.in_use -= 1;
+        self.active_objects -= 1;
+    }
+
+    fn find_partial(&self) -> Option<NonNull<SlabHeader>> {
+        let mut slab = self.slabs;
+        while let Some(current) = slab {
+            let header = unsafe { current.as_ref() };
+            if header.free.is_some() {
+                return Some(current);
+            }
+            slab = header.next;
+        }
+        None
+    }
+
+    /// Adds a slab with all objects free.
+    fn grow(&mut self) -> Result<NonNull<SlabHeader>, memory::Error> {
+        let frames = FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .allocate_contiguous(self.slab_frames, self.slab_frames)
+            .ok_or(memory::Error::OutOfFrames)?;
+        let start = memory::phys_to_virt(frames.start.start_address()).as_u64() as usize;
+
+        let mut free = None;
+        for index in (0..self.objects_per_slab).rev() {
+            let object = (start + self.first_object + index * self.object_size) as *mut FreeObject;
+            unsafe { object.write(FreeObject { next: free }) };
+            free = NonNull::new(object);
+        }
+
+        let header = start as *mut SlabHeader;
+        unsafe {
+            header.write(SlabHeader {
+                next: self.slabs,
+                free,
+                in_use: 0,
+            })
+        };
+
+        self.slabs = NonNull::new(header);
+        self.slab_count += 1;
+        Ok(self.slabs.unwrap())
+    }
+
+    /// Releases all slabs without live objects, returns how many were released.
+    fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        let mut link: *mut Option<NonNull<SlabHeader>> = &mut self.slabs;
+
+        unsafe {
+            while let Some(slab) = *link {
+                let header = slab.as_ptr();
+                if (*header).in_use > 0 {
+                    link = &mut (*header).next;
+                    continue;
+                }
+
+                *link = (*header).next;
+                let first =
+                    PhysFrame::containing_address(memory::virt_to_phys(VirtAddr::from_ptr(header)));
+                FRAME_ALLOCATOR
+                    .get()
+                    .unwrap()
+                    .lock()
+                    .deallocate_contiguous(PhysFrame::range(
+                        first,
+                        first + self.slab_frames as u64,
+                    ));
+                released += 1;
+            }
+        }
+
+        self.slab_count -= released;
+        released
+    }
+
+    /// Slabs are aligned to their size, so the header is found by masking the address.
+    fn slab_of(&self, ptr: NonNull<u8>) -> NonNull<SlabHeader> {
+        let phys = memory::virt_to_phys(VirtAddr::from_ptr(ptr.as_ptr())).as_u64();
+        let start = PhysAddr::new(phys & !(self.slab_size() as u64 - 1));
+        NonNull::new(memory::phys_to_virt(start).as_mut_ptr()).unwrap()
+    }
+
+    fn stats(&self) -> SlabStats {
+        SlabStats {
+            name: self.name,
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab,
+            slab_size: self.slab_size(),
+            slabs: self.slab_count,
+            active_objects: self.active_objects,
+        }
+    }
+}
+
+/// Occupancy of a single cache.
+#[derive(Debug, Clone)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slab_size: usize,
+    pub slabs: usize,
+    pub active_objects: usize,
+}
+
+impl SlabStats {
+    pub fn total_objects(&self) -> usize {
+        self.slabs * self.objects_per_slab
+    }
+
+    /// Share of object slots in use, in percent.
+    pub fn occupancy(&self) -> usize {
+        match self.total_objects() {
+            0 => 0,
+            total => self.active_objects * 100 / total,
+        }
+    }
+}
+
+impl fmt::Display for SlabStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>6} B objects : {}/{} in use ({}%), {} slabs of {:#x}",
+            self.name,
+            self.object_size,
+            self.active_objects,
+            self.total_objects(),
+            self.occupancy(),
+            self.slabs,
+            self.slab_size
+        )
+    }
+}
+
+/// Named cache handing out objects of type `T` from slabs of physical frames.
+///
+/// Caches are created once through [`SlabCache::create`] and live for the rest of
+/// the kernel's lifetime, so they can be listed with [`caches`].
+pub struct SlabCache<T> {
+    raw: Locked<RawCache>,
+    constructor: fn() -> T,
+    _marker: PhantomData<T>,
+}
+
+impl<T> SlabCache<T> {
+    /// Creates and registers a cache, `constructor` builds the objects returned by
+    /// [`alloc`](SlabCache::alloc).
+    pub fn create(name: &'static str, constructor: fn() -> T) -> &'static Self {
+        let cache: &'static Self = Box::leak(Box::new(SlabCache {
+            raw: Locked::new(RawCache::new(name, Layout::new::<T>())),
+            constructor,
+            _marker: PhantomData,
+        }));
+
+        CACHES.lock().push(&cache.raw);
+        cache
+    }
+
+    /// Allocates an object initialized by the cache's constructor.
+    pub fn alloc(&'static self) -> Result<SlabBox<T>, memory::Error> {
+        self.alloc_with((self.constructor)())
+    }
+
+    pub fn alloc_with(&'static self, value: T) -> Result<SlabBox<T>, memory::Error> {
+        let ptr = self.raw.lock().allocate()?.cast::<T>();
+        unsafe { ptr.as_ptr().write(value) };
+
+        Ok(SlabBox { cache: self, ptr })
+    }
+
+    /// Returns the empty slabs to the frame allocator.
+    pub fn shrink(&self) -> usize {
+        self.raw.lock().shrink()
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        self.raw.lock().stats()
+    }
+}
+
+/// Owning pointer to an object of a [`SlabCache`], the object goes back to the cache on drop.
+pub struct SlabBox<T: 'static> {
+    cache: &'static SlabCache<T>,
+    ptr: NonNull<T>,
+}
+
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+impl<T> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.raw.lock().deallocate(self.ptr.cast());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use core::{
+    alloc::Layout,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{
+    fixed_size::{list_index, BLOCK_SIZES},
+    heap_limit,
+};
+
+static BYTES_ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static BYTES_FREED: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static PEAK_USAGE: AtomicUsize = AtomicUsize::new(0);
+
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+/// Live allocations per entry of `BLOCK_SIZES`, with one extra slot for larger ones.
+static LIVE_BLOCKS: [AtomicUsize; BLOCK_SIZES.len() + 1] = [ZERO; BLOCK_SIZES.len() + 1];
+
+/// Allocators report what only they know about their heap through this trait.
+pub trait Inspect {
+    fn inspect(&self, stats: &mut HeapStats);
+}
+
+/// Snapshot of the kernel heap returned by [`stats`](super::stats).
+///
+/// The counters are kept for every allocator, the heap layout fields are left at
+/// zero when the active allocator cannot provide them.
+#[derive(Debug, Clone, Default)]
+pub struct HeapStats {
+    pub heap_size: usize,
+    pub heap_limit: usize,
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+    pub total_allocations: usize,
+    pub live_allocations: usize,
+    pub peak_usage: usize,
+    /// Live allocations per block size, the last entry counts allocations larger than any block.
+    pub live_blocks: [usize; BLOCK_SIZES.len() + 1],
+    /// Length of the free list of each block size.
+    pub free_blocks: [usize; BLOCK_SIZES.len()],
+    pub free_regions: usize,
+    pub free_bytes: usize,
+    pub largest_free_region: usize,
+}
+
+impl HeapStats {
+    pub fn in_use(&self) -> usize {
+        self.bytes_allocated - self.bytes_freed
+    }
+
+    /// Share of free memory in percent that is not part of the largest free region.
+    pub fn fragmentation(&self) -> usize {
+        if self.free_bytes == 0 {
+            return 0;
+        }
+        100 - self.largest_free_region * 100 / self.free_bytes
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Heap size       : {:#x} / {:#x}",
+            self.heap_size, self.heap_limit
+        )?;
+        writeln!(
+            f,
+            "In use          : {:#x} (peak {:#x})",
+            self.in_use(),
+            self.peak_usage
+        )?;
+        writeln!(f, "Allocated bytes : {:#x}", self.bytes_allocated)?;
+        writeln!(f, "Freed bytes     : {:#x}", self.bytes_freed)?;
+        writeln!(
+            f,
+            "Allocations     : {} live, {} total",
+            self.live_allocations, self.total_allocations
+        )?;
+        if self.free_regions > 0 {
+            writeln!(
+                f,
+                "Free regions    : {} ({:#x} bytes, largest {:#x}, {}% fragmented)",
+                self.free_regions,
+                self.free_bytes,
+                self.largest_free_region,
+                self.fragmentation()
+            )?;
+        }
+        for (index, size) in BLOCK_SIZES.iter().enumerate() {
+            writeln!(
+                f,
+                "Block {:>5}     : {} live, {} free",
+                size, self.live_blocks[index], self.free_blocks[index]
+            )?;
+        }
+        write!(
+            f,
+            "Larger blocks   : {} live",
+            self.live_blocks[BLOCK_SIZES.len()]
+        )
+    }
+}
+
+fn size_class(layout: Layout) -> usize {
+    list_index(layout).unwrap_or(BLOCK_SIZES.len())
+}
+
+pub(super) fn record_alloc(layout: Layout) {
+    let allocated = BYTES_ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+    let in_use = allocated - BYTES_FREED.load(Ordering::Relaxed);
+
+    PEAK_USAGE.fetch_max(in_use, Ordering::Relaxed);
+    TOTAL_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    LIVE_BLOCKS[size_class(layout)].fetch_add(1, Ordering::Relaxed);
+}
+
+pub(super) fn record_dealloc(layout: Layout) {
+    BYTES_FREED.fetch_add(layout.size(), Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+    LIVE_BLOCKS[size_class(layout)].fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Takes back a [`record_dealloc`] made for a block the heap-debug quarantine kept,
+/// right before the inner allocator frees it for real.
+#[cfg(feature = "heap-debug")]
+pub(super) fn unrecord_dealloc(layout: Layout) {
+    BYTES_FREED.fetch_sub(layout.size(), Ordering::Relaxed);
+    LIVE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    LIVE_BLOCKS[size_class(layout)].fetch_add(1, Ordering::Relaxed);
+}
+
+pub(super) fn collect(allocator: &impl Inspect) -> HeapStats {
+    let mut stats = HeapStats {
+        heap_limit: heap_limit(),
+        bytes_allocated: BYTES_ALLOCATED.load(Ordering::Relaxed),
+        bytes_freed: BYTES_FREED.load(Ordering::Relaxed),
+        total_allocations: TOTAL_ALLOCATIONS.load(Ordering::Relaxed),
+        live_allocations: LIVE_ALLOCATIONS.load(Ordering::Relaxed),
+        peak_usage: PEAK_USAGE.load(Ordering::Relaxed),
+        ..HeapStats::default()
+    };
+
+    for (count, live) in stats.live_blocks.iter_mut().zip(LIVE_BLOCKS.iter()) {
+        *count = live.load(Ordering::Relaxed);
+    }
+
+    allocator.inspect(&mut stats);
+    stats
+}
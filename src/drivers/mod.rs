@@ -2,7 +2,7 @@ use alloc::{sync::Arc, vec::Vec};
 use conquer_once::spin::OnceCell;
 use pci::{get_pci_devices, Pci};
 mod network;
-mod pci;
+pub mod pci;
 mod storage;
 
 pub trait Driver: Sync + Send {
@@ -1,37 +1,74 @@
 use alloc::vec::Vec;
 use conquer_once::spin::OnceCell;
 use core::fmt;
-use x86_64::{structures::paging::FrameAllocator, PhysAddr, VirtAddr};
+use x86_64::VirtAddr;
 
 use crate::{
-    drivers::pci::Bar, memory::FRAME_ALLOCATOR, phys_to_virt_addr, read_virt_addr, ReadError,
+    interrupts::{self, IrqResult},
+    memory::{self, DmaBuffer, MmioRegion},
+    ReadError,
 };
 
 use super::{
-    pci::{ClassCode, Pci},
+    pci::{ClassCode, MsiInterrupt, Pci},
     Driver, PCI_DEVICES,
 };
 
 const TX_DESC_NUM: u32 = 32;
 const TX_DESC_SIZE: u32 = TX_DESC_NUM * core::mem::size_of::<TxRegister>() as u32;
 
+// Interrupt causes.
+const INT_TX_DESC_WRITTEN: u32 = 1 << 0;
+const INT_LINK_STATUS_CHANGE: u32 = 1 << 2;
+const INT_RX_TIMER: u32 = 1 << 7;
+
 pub(super) static NETWORK_DEVICES: OnceCell<Vec<NetworkDriver>> = OnceCell::uninit();
+/// Devices that support MSI use it, the others share their legacy lines.
+static NETWORK_MSI: OnceCell<Vec<MsiInterrupt<'static>>> = OnceCell::uninit();
 
 pub fn init() -> Result<(), Error> {
     let mut network_devices = Vec::new();
+    let mut msi = Vec::new();
+    let mut lines = Vec::new();
     for pci in PCI_DEVICES.get().unwrap() {
         if let ClassCode::Network(_) = pci.header.class_code {
             network_devices.push(NetworkDriver::new(pci)?);
+            match pci.enable_msi(interrupt_handler) {
+                Ok(interrupt) => msi.push(interrupt),
+                Err(_) => lines.extend(pci.interrupt_line()),
+            }
         }
     }
     NETWORK_DEVICES.init_once(|| network_devices);
+    NETWORK_MSI.init_once(|| msi);
+
+    lines.sort_unstable();
+    lines.dedup();
+    for line in lines {
+        interrupts::register_irq(line, interrupt_handler)?;
+    }
+    for device in NETWORK_DEVICES.get().unwrap() {
+        device.enable_interrupts();
+    }
 
     Ok(())
 }
 
+/// Shared by all network devices, the cause register tells which device fired.
+fn interrupt_handler(_line: u8) -> IrqResult {
+    let mut result = IrqResult::NotMine;
+    for device in NETWORK_DEVICES.get().into_iter().flatten() {
+        if device.read(Register::InterruptCause) != 0 {
+            result = IrqResult::Handled;
+        }
+    }
+    result
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Error {
     OutOfMemory,
+    Interrupt(interrupts::IrqError),
 }
 
 impl From<ReadError> for Error {
@@ -40,6 +77,18 @@ impl From<ReadError> for Error {
     }
 }
 
+impl From<interrupts::IrqError> for Error {
+    fn from(value: interrupts::IrqError) -> Self {
+        Error::Interrupt(value)
+    }
+}
+
+impl From<memory::Error> for Error {
+    fn from(_value: memory::Error) -> Self {
+        Error::OutOfMemory
+    }
+}
+
 pub enum NetworkSubClass {
     Ethernet,
     TokenRing,
@@ -92,6 +141,13 @@ impl fmt::Debug for NetworkSubClass {
 
 enum Register {
     Eeprom = 0x14,
+    InterruptCause = 0xc0,
+    InterruptMaskSet = 0xd0,
+    TxDescLow = 0x3800,
+    TxDescHigh = 0x3804,
+    TxDescLen = 0x3808,
+    TxDescHead = 0x3810,
+    TxDescTail = 0x3818,
 }
 
 impl Driver for NetworkDriver {
@@ -124,10 +180,10 @@ struct TxRegister {
 }
 
 pub struct NetworkDriver {
-    base_register: VirtAddr,
+    registers: MmioRegion,
     mac: [u8; 6],
     tx_curr: usize,
-    tx_ring: VirtAddr,
+    tx_ring: DmaBuffer<[TxRegister]>,
     rx_curr: usize,
     rx_ring: VirtAddr,
     eeprom: bool,
@@ -135,16 +191,10 @@ pub struct NetworkDriver {
 
 impl NetworkDriver {
     fn write(&self, register: Register, value: u32) {
-        unsafe {
-            let register = self.base_register.as_mut_ptr::<u8>().add(register as usize);
-            core::ptr::write_volatile(register as *mut u32, value);
-        }
+        self.registers.write32(register as usize, value)
     }
     fn read(&self, register: Register) -> u32 {
-        unsafe {
-            let register = self.base_register.as_mut_ptr::<u8>().add(register as usize);
-            core::ptr::read_volatile(register as *const u32)
-        }
+        self.registers.read32(register as usize)
     }
     fn detect_eeprom(&self) -> bool {
         self.write(Register::Eeprom, 0x1);
@@ -176,39 +226,45 @@ impl NetworkDriver {
         mac
     }
 
-    fn init_tx(&mut self) -> Result<(), Error> {
-        let mut frame_allocator = FRAME_ALLOCATOR.try_get().unwrap().lock();
+    fn init_tx(&mut self) {
+        let phys = self.tx_ring.phys_addr().as_u64();
 
-        let frame = frame_allocator.allocate_frame().unwrap();
-        let phys = frame.start_address();
-        let mut addr = phys_to_virt_addr(phys);
-        let descriptors = read_virt_addr::<[TxRegister; TX_DESC_NUM as usize]>(&mut addr)?;
-        Ok(())
+        self.write(Register::TxDescLow, phys as u32);
+        self.write(Register::TxDescHigh, (phys >> 32) as u32);
+        self.write(Register::TxDescLen, TX_DESC_SIZE);
+        self.write(Register::TxDescHead, 0);
+        self.write(Register::TxDescTail, 0);
+        self.tx_curr = 0;
+    }
+
+    fn enable_interrupts(&self) {
+        self.write(
+            Register::InterruptMaskSet,
+            INT_TX_DESC_WRITTEN | INT_LINK_STATUS_CHANGE | INT_RX_TIMER,
+        );
+        // Reading the cause register clears pending interrupts.
+        self.read(Register::InterruptCause);
     }
 
     pub fn new(pci: &Pci) -> Result<Self, Error> {
         pci.enable_mmio();
         pci.enable_bus_mastering();
-        let bar = pci.get_bar(0);
-
-        let phys_addr = match bar {
-            Some(Bar::Memory32 { address, .. }) => PhysAddr::new(address as u64),
-            Some(Bar::Memory64 { address, .. }) => PhysAddr::new(address),
-            _ => panic!("Unknown Base Address register for Network Driver"),
+        let registers = match pci.get_bar(0) {
+            Some(bar) => bar.map()?,
+            None => panic!("Unknown Base Address register for Network Driver"),
         };
-        let base_register = phys_to_virt_addr(phys_addr);
 
         let mut this = Self {
-            base_register,
+            registers,
             mac: [0; 6],
             tx_curr: 0,
-            tx_ring: VirtAddr::zero(),
+            tx_ring: DmaBuffer::new_slice(TX_DESC_NUM as usize)?,
             rx_curr: 0,
             rx_ring: VirtAddr::zero(),
             eeprom: false,
         };
 
-        this.init_tx()?;
+        this.init_tx();
 
         this.eeprom = this.detect_eeprom();
         this.mac = this.get_mac_address();
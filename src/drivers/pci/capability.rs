@@ -0,0 +1,79 @@
+use super::{PCIConfigRegisters, Pci};
+
+/// Status register bit telling that the capabilities pointer is valid.
+const STATUS_CAPABILITIES_LIST: u16 = 1 << 4;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CapabilityId {
+    PowerManagement = 0x01,
+    Msi = 0x05,
+    VendorSpecific = 0x09,
+    PciExpress = 0x10,
+    MsiX = 0x11,
+}
+
+/// Entry of the capability list in the configuration space of a device.
+#[derive(Debug, Clone, Copy)]
+pub struct Capability {
+    pub id: u8,
+    /// Offset of the capability in the configuration space.
+    pub offset: u8,
+}
+
+impl Capability {
+    pub fn is(&self, id: CapabilityId) -> bool {
+        self.id == id as u8
+    }
+}
+
+/// Walks the capability list of a device, see [`Pci::capabilities`].
+pub struct Capabilities<'a> {
+    pci: &'a Pci,
+    next: u8,
+    /// Bounds the walk in case the list of a broken device loops.
+    remaining: usize,
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = Capability;
+
+    fn next(&mut self) -> Option<Capability> {
+        // The bottom two bits are reserved, offsets below 0x40 are the header.
+        let offset = self.next & !0b11;
+        if offset < 0x40 || self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let header = self.pci.config_read_u16(offset);
+        self.next = (header >> 8) as u8;
+
+        Some(Capability {
+            id: header as u8,
+            offset,
+        })
+    }
+}
+
+impl Pci {
+    pub fn capabilities(&self) -> Capabilities<'_> {
+        let status = self.config_read_u16(PCIConfigRegisters::PCIStatus as u8);
+        let next = if status & STATUS_CAPABILITIES_LIST != 0 {
+            self.config_read_u8(PCIConfigRegisters::PCICapabilitiesPointer as u8)
+        } else {
+            0
+        };
+
+        Capabilities {
+            pci: self,
+            next,
+            remaining: 48,
+        }
+    }
+
+    pub fn find_capability(&self, id: CapabilityId) -> Option<Capability> {
+        self.capabilities().find(|capability| capability.is(id))
+    }
+}
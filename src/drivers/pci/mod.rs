@@ -1,8 +1,17 @@
+mod capability;
+mod msi;
+
+pub use capability::{Capabilities, Capability, CapabilityId};
+pub use msi::{
+    Error as MsiError, MsiCapability, MsiInterrupt, MsixCapability, MsixInterrupts, MsixTable,
+};
+
 use super::{network::NetworkSubClass, storage::StorageSubclass};
+use crate::memory::{self, map_mmio, MmioRegion};
 use alloc::vec::Vec;
 use bit_field::BitField;
 use core::fmt;
-use x86_64::instructions::port::Port;
+use x86_64::{instructions::port::Port, PhysAddr};
 
 pub(super) fn get_pci_devices() -> Vec<Pci> {
     let mut devices = Vec::new();
@@ -329,6 +338,19 @@ pub enum Bar {
     Io(u32),
 }
 
+impl Bar {
+    /// Maps the registers of a memory BAR, I/O BARs cannot be mapped.
+    pub fn map(&self) -> Result<MmioRegion, memory::Error> {
+        match *self {
+            Bar::Memory32 { address, size, .. } => {
+                map_mmio(PhysAddr::new(address as u64), size as usize)
+            }
+            Bar::Memory64 { address, size, .. } => map_mmio(PhysAddr::new(address), size as usize),
+            Bar::Io(_) => Err(memory::Error::InvalidRegion),
+        }
+    }
+}
+
 pub struct Pci {
     bus: u8,
     slot: u8,
@@ -409,6 +431,14 @@ impl Pci {
         self.config_write_u16(0x4, command | (1 << 2));
     }
 
+    /// Legacy interrupt line the firmware assigned to the device.
+    pub fn interrupt_line(&self) -> Option<u8> {
+        self.header
+            .non_bridge_header
+            .as_ref()
+            .and_then(|header| header.interrupt_line)
+    }
+
     pub fn enable_mmio(&self) {
         let command = self.config_read_u16(0x4);
         self.config_write_u16(0x4, command | (1 << 1));
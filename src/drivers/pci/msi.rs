@@ -0,0 +1,341 @@
+use alloc::vec::Vec;
+
+use super::{capability::CapabilityId, PCIConfigRegisters, Pci};
+use crate::{
+    interrupts::{self, apic, irq_vector, IrqError, IrqHandler},
+    memory::{self, MmioRegion},
+};
+
+const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+// MSI message control.
+const MSI_ENABLE: u16 = 1 << 0;
+const MSI_MULTIPLE_ENABLE: u16 = 0b111 << 4;
+const MSI_64BIT: u16 = 1 << 7;
+const MSI_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+// MSI-X message control.
+const MSIX_TABLE_SIZE: u16 = 0x7ff;
+const MSIX_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_ENABLE: u16 = 1 << 15;
+
+const MSIX_ENTRY_SIZE: usize = 16;
+const MSIX_ENTRY_ADDRESS_LOW: usize = 0x0;
+const MSIX_ENTRY_ADDRESS_HIGH: usize = 0x4;
+const MSIX_ENTRY_DATA: usize = 0x8;
+const MSIX_ENTRY_CONTROL: usize = 0xc;
+const MSIX_ENTRY_MASKED: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Error {
+    /// The device lacks the capability, or a table BAR that can be mapped.
+    NotSupported,
+    /// Messages are delivered to a local APIC, so it must be enabled.
+    NoApic,
+    NoFreeLine,
+    Irq(IrqError),
+    Memory(memory::Error),
+}
+
+impl From<IrqError> for Error {
+    fn from(value: IrqError) -> Self {
+        Error::Irq(value)
+    }
+}
+
+impl From<memory::Error> for Error {
+    fn from(value: memory::Error) -> Self {
+        Error::Memory(value)
+    }
+}
+
+/// MSI capability of a device.
+#[derive(Debug, Clone, Copy)]
+pub struct MsiCapability {
+    offset: u8,
+    control: u16,
+}
+
+impl MsiCapability {
+    pub fn is_64bit(&self) -> bool {
+        self.control & MSI_64BIT != 0
+    }
+
+    pub fn per_vector_masking(&self) -> bool {
+        self.control & MSI_PER_VECTOR_MASKING != 0
+    }
+
+    /// Vectors the device can request, only one of them is ever enabled.
+    pub fn max_vectors(&self) -> usize {
+        1 << ((self.control >> 1) & 0b111)
+    }
+
+    fn data_offset(&self) -> u8 {
+        if self.is_64bit() {
+            self.offset + 0xc
+        } else {
+            self.offset + 0x8
+        }
+    }
+
+    fn program(&self, pci: &Pci, address: u64, data: u32) {
+        pci.config_write_u32(self.offset + 0x4, address as u32);
+        if self.is_64bit() {
+            pci.config_write_u32(self.offset + 0x8, (address >> 32) as u32);
+        }
+        pci.config_write_u16(self.data_offset(), data as u16);
+        if self.per_vector_masking() {
+            pci.config_write_u32(self.data_offset() + 0x4, 0);
+        }
+    }
+
+    fn set_enabled(&self, pci: &Pci, enabled: bool) {
+        let control = pci.config_read_u16(self.offset + 0x2) & !MSI_MULTIPLE_ENABLE;
+        let control = if enabled {
+            control | MSI_ENABLE
+        } else {
+            control & !MSI_ENABLE
+        };
+        pci.config_write_u16(self.offset + 0x2, control);
+    }
+}
+
+/// MSI-X capability of a device.
+#[derive(Debug, Clone, Copy)]
+pub struct MsixCapability {
+    offset: u8,
+    table_size: usize,
+    table_bar: u8,
+    table_offset: u32,
+}
+
+impl MsixCapability {
+    pub fn table_size(&self) -> usize {
+        self.table_size
+    }
+
+    /// Maps the BAR holding the vector table.
+    pub fn map_table(&self, pci: &Pci) -> Result<MsixTable, Error> {
+        let bar = pci.get_bar(self.table_bar).ok_or(Error::NotSupported)?;
+
+        Ok(MsixTable {
+            registers: bar.map()?,
+            offset: self.table_offset as usize,
+            len: self.table_size,
+        })
+    }
+
+    fn set_control(&self, pci: &Pci, enabled: bool, function_masked: bool) {
+        let mut control = pci.config_read_u16(self.offset + 0x2);
+        control &= !(MSIX_ENABLE | MSIX_FUNCTION_MASK);
+        if enabled {
+            control |= MSIX_ENABLE;
+        }
+        if function_masked {
+            control |= MSIX_FUNCTION_MASK;
+        }
+        pci.config_write_u16(self.offset + 0x2, control);
+    }
+}
+
+/// Vector table of an MSI-X capability.
+pub struct MsixTable {
+    registers: MmioRegion,
+    offset: usize,
+    len: usize,
+}
+
+impl MsixTable {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn entry(&self, index: usize) -> usize {
+        assert!(index < self.len, "MSI-X entry {} out of range", index);
+        self.offset + index * MSIX_ENTRY_SIZE
+    }
+
+    pub fn program(&self, index: usize, address: u64, data: u32) {
+        let entry = self.entry(index);
+        self.registers
+            .write32(entry + MSIX_ENTRY_ADDRESS_LOW, address as u32);
+        self.registers
+            .write32(entry + MSIX_ENTRY_ADDRESS_HIGH, (address >> 32) as u32);
+        self.registers.write32(entry + MSIX_ENTRY_DATA, data);
+    }
+
+    pub fn set_masked(&self, index: usize, masked: bool) {
+        let entry = self.entry(index) + MSIX_ENTRY_CONTROL;
+        let control = self.registers.read32(entry);
+        self.registers.write32(
+            entry,
+            if masked {
+                control | MSIX_ENTRY_MASKED
+            } else {
+                control & !MSIX_ENTRY_MASKED
+            },
+        );
+    }
+}
+
+/// Reserves an MSI line for `handler`, returns it with the message to program.
+fn allocate_line(handler: IrqHandler) -> Result<(u8, u64, u32), Error> {
+    let destination = apic::local_apic_id().ok_or(Error::NoApic)?;
+    let line = interrupts::allocate_msi_line().ok_or(Error::NoFreeLine)?;
+
+    if let Err(error) = interrupts::register_irq(line, handler) {
+        interrupts::free_msi_line(line);
+        return Err(error.into());
+    }
+
+    let (address, data) = apic::msi_message(irq_vector(line), destination);
+    Ok((line, address, data))
+}
+
+fn release_line(line: u8, handler: IrqHandler) {
+    interrupts::unregister_irq(line, handler).unwrap();
+    interrupts::free_msi_line(line);
+}
+
+fn set_intx_disabled(pci: &Pci, disabled: bool) {
+    let command = pci.config_read_u16(PCIConfigRegisters::PCICommand as u8);
+    let command = if disabled {
+        command | COMMAND_INTX_DISABLE
+    } else {
+        command & !COMMAND_INTX_DISABLE
+    };
+    pci.config_write_u16(PCIConfigRegisters::PCICommand as u8, command);
+}
+
+/// MSI of a device delivered to a line of the kernel's IRQ dispatch.
+///
+/// Dropping it disables MSI and gives the device its legacy interrupt back.
+pub struct MsiInterrupt<'a> {
+    pci: &'a Pci,
+    capability: MsiCapability,
+    line: u8,
+    handler: IrqHandler,
+}
+
+impl MsiInterrupt<'_> {
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+}
+
+impl Drop for MsiInterrupt<'_> {
+    fn drop(&mut self) {
+        self.capability.set_enabled(self.pci, false);
+        set_intx_disabled(self.pci, false);
+        release_line(self.line, self.handler);
+    }
+}
+
+/// MSI-X vectors of a device, each delivered to its own line.
+///
+/// Dropping it masks the vectors and disables MSI-X.
+pub struct MsixInterrupts<'a> {
+    pci: &'a Pci,
+    capability: MsixCapability,
+    table: MsixTable,
+    lines: Vec<(u8, IrqHandler)>,
+}
+
+impl MsixInterrupts<'_> {
+    /// Line of each vector, in the order of the handlers.
+    pub fn lines(&self) -> impl Iterator<Item = u8> + '_ {
+        self.lines.iter().map(|&(line, _)| line)
+    }
+
+    pub fn table(&self) -> &MsixTable {
+        &self.table
+    }
+}
+
+impl Drop for MsixInterrupts<'_> {
+    fn drop(&mut self) {
+        for (index, &(line, handler)) in self.lines.iter().enumerate() {
+            self.table.set_masked(index, true);
+            release_line(line, handler);
+        }
+        self.capability.set_control(self.pci, false, false);
+        set_intx_disabled(self.pci, false);
+    }
+}
+
+impl Pci {
+    pub fn msi(&self) -> Option<MsiCapability> {
+        let capability = self.find_capability(CapabilityId::Msi)?;
+
+        Some(MsiCapability {
+            offset: capability.offset,
+            control: self.config_read_u16(capability.offset + 0x2),
+        })
+    }
+
+    pub fn msix(&self) -> Option<MsixCapability> {
+        let capability = self.find_capability(CapabilityId::MsiX)?;
+        let control = self.config_read_u16(capability.offset + 0x2);
+        let table = self.config_read_u32(capability.offset + 0x4);
+
+        Some(MsixCapability {
+            offset: capability.offset,
+            table_size: (control & MSIX_TABLE_SIZE) as usize + 1,
+            table_bar: (table & 0b111) as u8,
+            table_offset: table & !0b111,
+        })
+    }
+
+    /// Delivers the MSI of the device to `handler` instead of its legacy line.
+    pub fn enable_msi(&self, handler: IrqHandler) -> Result<MsiInterrupt<'_>, Error> {
+        let capability = self.msi().ok_or(Error::NotSupported)?;
+        let (line, address, data) = allocate_line(handler)?;
+
+        capability.program(self, address, data);
+        capability.set_enabled(self, true);
+        set_intx_disabled(self, true);
+
+        Ok(MsiInterrupt {
+            pci: self,
+            capability,
+            line,
+            handler,
+        })
+    }
+
+    /// Enables MSI-X with one vector per handler, vector `i` calls `handlers[i]`.
+    pub fn enable_msix(&self, handlers: &[IrqHandler]) -> Result<MsixInterrupts<'_>, Error> {
+        let capability = self.msix().ok_or(Error::NotSupported)?;
+        if handlers.len() > capability.table_size() {
+            return Err(Error::NotSupported);
+        }
+
+        let table = capability.map_table(self)?;
+
+        // Some devices only accept table writes while MSI-X is enabled, the
+        // function mask holds back interrupts until all vectors are set up.
+        capability.set_control(self, true, true);
+        let mut interrupts = MsixInterrupts {
+            pci: self,
+            capability,
+            table,
+            lines: Vec::with_capacity(handlers.len()),
+        };
+
+        for (index, &handler) in handlers.iter().enumerate() {
+            let (line, address, data) = allocate_line(handler)?;
+            interrupts.lines.push((line, handler));
+            interrupts.table.program(index, address, data);
+            interrupts.table.set_masked(index, false);
+        }
+
+        set_intx_disabled(self, true);
+        capability.set_control(self, true, false);
+
+        Ok(interrupts)
+    }
+}
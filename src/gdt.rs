@@ -1,3 +1,4 @@
+use alloc::{boxed::Box, vec};
 use lazy_static::lazy_static;
 use x86_64::{
     structures::{
@@ -8,33 +9,16 @@ use x86_64::{
 };
 
 pub const DOUBLE_FAULT_INDEX: u16 = 0;
+const DOUBLE_FAULT_STACK_SIZE: usize = 4096 * 5;
 
 lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, Selectors) = {
-        let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (
-            gdt,
-            Selectors {
-                code_selector,
-                tss_selector,
-            },
-        )
-    };
+    static ref GDT: (GlobalDescriptorTable, Selectors) = new_gdt(&TSS);
 }
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
+        static mut STACK: [u8; DOUBLE_FAULT_STACK_SIZE] = [0; DOUBLE_FAULT_STACK_SIZE];
+        new_tss(VirtAddr::from_ptr(unsafe { &STACK }))
     };
 }
 
@@ -43,12 +27,44 @@ pub struct Selectors {
     tss_selector: SegmentSelector,
 }
 
-pub fn init() {
+fn new_tss(double_fault_stack: VirtAddr) -> TaskStateSegment {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_INDEX as usize] =
+        double_fault_stack + DOUBLE_FAULT_STACK_SIZE;
+    tss
+}
+
+fn new_gdt(tss: &'static TaskStateSegment) -> (GlobalDescriptorTable, Selectors) {
+    let mut gdt = GlobalDescriptorTable::new();
+    let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+    (
+        gdt,
+        Selectors {
+            code_selector,
+            tss_selector,
+        },
+    )
+}
+
+fn load(gdt: &'static (GlobalDescriptorTable, Selectors)) {
     use x86_64::instructions::segmentation::{Segment, CS};
     use x86_64::instructions::tables::load_tss;
-    GDT.0.load();
+    gdt.0.load();
     unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        load_tss(GDT.1.tss_selector);
+        CS::set_reg(gdt.1.code_selector);
+        load_tss(gdt.1.tss_selector);
     }
 }
+
+pub fn init() {
+    load(&GDT);
+}
+
+/// Loads a GDT and TSS of its own on an application processor, a TSS is busy
+/// once loaded and cannot be shared. Needs the heap.
+pub fn init_ap() {
+    let stack = Box::leak(vec![0u8; DOUBLE_FAULT_STACK_SIZE].into_boxed_slice());
+    let tss = Box::leak(Box::new(new_tss(VirtAddr::from_ptr(stack.as_ptr()))));
+    load(Box::leak(Box::new(new_gdt(tss))));
+}
@@ -0,0 +1,459 @@
+use conquer_once::spin::OnceCell;
+use core::{
+    arch::x86_64::__cpuid,
+    sync::atomic::{AtomicU16, Ordering},
+};
+use spin::Mutex;
+use x86_64::{registers::model_specific::Msr, PhysAddr};
+
+use super::{
+    irq::{self, IRQ_TIMER, LEGACY_LINES},
+    irq_vector, InterruptIndex, PICS,
+};
+use crate::{
+    acpi::{Madt, MadtEntry},
+    memory::{self, map_mmio, MmioRegion},
+};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Where the firmware places the first I/O APIC, used when there is no MADT.
+pub const DEFAULT_IO_APIC_ADDR: u64 = 0xfec0_0000;
+
+// Local APIC registers.
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SPURIOUS: usize = 0xf0;
+const LAPIC_ICR_LOW: usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_LVT_LINT0: usize = 0x350;
+const LAPIC_LVT_LINT1: usize = 0x360;
+const LAPIC_LVT_ERROR: usize = 0x370;
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+// Interrupt command register fields.
+const ICR_DELIVERY_INIT: u32 = 0b101 << 8;
+const ICR_DELIVERY_STARTUP: u32 = 0b110 << 8;
+const ICR_SEND_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+
+// I/O APIC registers, accessed through the select and window registers.
+const IOAPIC_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_ID: u32 = 0x00;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+
+const MSI_ADDRESS_BASE: u64 = 0xfee0_0000;
+
+static LOCAL_APIC: OnceCell<LocalApic> = OnceCell::uninit();
+static IO_APIC: OnceCell<Mutex<IoApic>> = OnceCell::uninit();
+
+/// ISA IRQ to global system interrupt mapping, `None` means identity mapped.
+///
+/// The PIT is wired to input 2 of the I/O APIC on virtually every PC, so that
+/// override is assumed until the firmware tables say otherwise.
+static ISA_OVERRIDES: Mutex<[Option<IrqWiring>; 16]> = Mutex::new({
+    let mut overrides = [None; 16];
+    overrides[IRQ_TIMER as usize] = Some(IrqWiring {
+        gsi: 2,
+        polarity: Polarity::ActiveHigh,
+        trigger: TriggerMode::Edge,
+    });
+    overrides
+});
+/// ISA IRQs whose wiring was given through [`set_isa_override`], one bit each.
+/// The MADT does not replace those.
+static CALLER_OVERRIDES: AtomicU16 = AtomicU16::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+/// Input of the I/O APIC an interrupt line is wired to.
+#[derive(Debug, Clone, Copy)]
+pub struct IrqWiring {
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+}
+
+impl IrqWiring {
+    /// Wiring of an ISA IRQ described with the MPS INTI flags of the MADT, where
+    /// "conforms to the bus" means active high and edge triggered.
+    fn from_mps_flags(gsi: u32, flags: u16) -> Self {
+        IrqWiring {
+            gsi,
+            polarity: if flags & 0b11 == 0b11 {
+                Polarity::ActiveLow
+            } else {
+                Polarity::ActiveHigh
+            },
+            trigger: if (flags >> 2) & 0b11 == 0b11 {
+                TriggerMode::Level
+            } else {
+                TriggerMode::Edge
+            },
+        }
+    }
+}
+
+/// Whether the CPU has a local APIC, according to CPUID.
+pub fn is_supported() -> bool {
+    let features = unsafe { __cpuid(1) };
+    features.edx & (1 << 9) != 0
+}
+
+/// Whether interrupts are delivered through the APIC instead of the 8259.
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.is_initialized()
+}
+
+/// Records how an ISA IRQ is wired, must be called before [`init`] to take effect.
+/// Takes precedence over what the MADT says about the IRQ.
+pub fn set_isa_override(irq: u8, wiring: IrqWiring) {
+    ISA_OVERRIDES.lock()[irq as usize] = Some(wiring);
+    CALLER_OVERRIDES.fetch_or(1 << irq, Ordering::Relaxed);
+}
+
+/// Switches interrupt delivery from the 8259 to the local and I/O APIC.
+///
+/// Needs `memory::init` for the register mappings. The I/O APIC and the ISA
+/// overrides come from the MADT once `acpi::init` has run, for the IRQs not
+/// given to [`set_isa_override`]. Returns `false` and
+/// leaves the 8259 in charge when there is no APIC.
+pub fn init() -> Result<bool, memory::Error> {
+    if is_enabled() {
+        return Ok(true);
+    }
+    let madt = match Madt::get() {
+        Some(madt) => madt,
+        None => return init_with(PhysAddr::new(DEFAULT_IO_APIC_ADDR)),
+    };
+
+    // Only the I/O APIC with the ISA IRQs is used.
+    let mut io_apic_addr = PhysAddr::new(DEFAULT_IO_APIC_ADDR);
+    let mut firmware_overrides = [None; 16];
+    for entry in madt.entries() {
+        match entry {
+            MadtEntry::IoApic {
+                address,
+                gsi_base: 0,
+                ..
+            } => io_apic_addr = PhysAddr::new(address as u64),
+            MadtEntry::InterruptOverride {
+                bus: 0,
+                source,
+                gsi,
+                flags,
+            } if source < 16 => {
+                firmware_overrides[source as usize] = Some(IrqWiring::from_mps_flags(gsi, flags))
+            }
+            _ => {}
+        }
+    }
+
+    let from_caller = CALLER_OVERRIDES.load(Ordering::Relaxed);
+    for (irq, wiring) in ISA_OVERRIDES.lock().iter_mut().enumerate() {
+        if from_caller & (1 << irq) == 0 {
+            *wiring = firmware_overrides[irq];
+        }
+    }
+
+    init_with(io_apic_addr)
+}
+
+/// Like [`init`], with the I/O APIC at `io_apic_addr`.
+pub fn init_with(io_apic_addr: PhysAddr) -> Result<bool, memory::Error> {
+    if !is_supported() || is_enabled() {
+        return Ok(is_enabled());
+    }
+
+    let local_apic = LocalApic::new()?;
+    let mut io_apic = IoApic::new(io_apic_addr)?;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        unsafe { PICS.lock().disable() };
+        local_apic.enable();
+
+        // Lines stay masked until a handler is registered for them.
+        for line in 0..LEGACY_LINES as u8 {
+            io_apic.route_line(line, local_apic.id(), !irq::has_handlers(line));
+        }
+
+        IO_APIC.init_once(|| Mutex::new(io_apic));
+        LOCAL_APIC.init_once(|| local_apic);
+    });
+
+    Ok(true)
+}
+
+/// Signals the end of the current interrupt to the local APIC.
+pub fn end_of_interrupt() {
+    if let Some(local_apic) = LOCAL_APIC.get() {
+        local_apic.end_of_interrupt();
+    }
+}
+
+/// Masks or unmasks an interrupt line at the I/O APIC.
+pub fn set_irq_masked(line: u8, masked: bool) {
+    if let Some(io_apic) = IO_APIC.get() {
+        io_apic.lock().set_line_masked(line, masked);
+    }
+}
+
+/// Address and data of a message signaled interrupt that delivers `vector` to the
+/// local APIC `destination`, edge triggered in fixed mode.
+pub fn msi_message(vector: u8, destination: u8) -> (u64, u32) {
+    (MSI_ADDRESS_BASE | (destination as u64) << 12, vector as u32)
+}
+
+/// ID of the local APIC of the current CPU.
+pub fn local_apic_id() -> Option<u8> {
+    LOCAL_APIC.get().map(LocalApic::id)
+}
+
+/// Enables the local APIC of an application processor once [`init`] ran on the
+/// bootstrap processor. Every CPU sees its own local APIC at the same address.
+pub fn init_ap() -> bool {
+    LOCAL_APIC.get().map(LocalApic::enable).is_some()
+}
+
+/// Sends interrupt `vector` to the CPU with local APIC `destination`.
+pub fn send_ipi(destination: u8, vector: u8) {
+    if let Some(local_apic) = LOCAL_APIC.get() {
+        local_apic.send_ipi(destination, vector as u32);
+    }
+}
+
+/// Sends an INIT IPI, which resets the CPU with local APIC `destination` into
+/// the wait-for-SIPI state.
+pub fn send_init(destination: u8) {
+    if let Some(local_apic) = LOCAL_APIC.get() {
+        local_apic.send_ipi(destination, ICR_DELIVERY_INIT | ICR_LEVEL_ASSERT);
+    }
+}
+
+/// Sends a startup IPI, the CPU starts in real mode at `page * 4096`.
+pub fn send_startup(destination: u8, page: u8) {
+    if let Some(local_apic) = LOCAL_APIC.get() {
+        local_apic.send_ipi(
+            destination,
+            ICR_DELIVERY_STARTUP | ICR_LEVEL_ASSERT | page as u32,
+        );
+    }
+}
+
+pub struct LocalApic {
+    registers: MmioRegion,
+}
+
+impl LocalApic {
+    /// Maps the registers at the address found in `IA32_APIC_BASE`.
+    fn new() -> Result<Self, memory::Error> {
+        let base = unsafe { Msr::new(IA32_APIC_BASE).read() };
+        let registers = map_mmio(PhysAddr::new(base & APIC_BASE_ADDR_MASK), 0x400)?;
+
+        if base & APIC_BASE_ENABLE == 0 {
+            unsafe { Msr::new(IA32_APIC_BASE).write(base | APIC_BASE_ENABLE) };
+        }
+
+        Ok(LocalApic { registers })
+    }
+
+    fn enable(&self) {
+        self.registers.write32(LAPIC_TPR, 0);
+        self.registers.write32(LAPIC_LVT_TIMER, LVT_MASKED);
+        self.registers.write32(LAPIC_LVT_LINT0, LVT_MASKED);
+        self.registers.write32(LAPIC_LVT_LINT1, LVT_MASKED);
+        self.registers.write32(LAPIC_LVT_ERROR, LVT_MASKED);
+        self.registers.write32(
+            LAPIC_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | InterruptIndex::ApicSpurious.as_u8() as u32,
+        );
+    }
+
+    pub fn id(&self) -> u8 {
+        (self.registers.read32(LAPIC_ID) >> 24) as u8
+    }
+
+    pub fn end_of_interrupt(&self) {
+        self.registers.write32(LAPIC_EOI, 0);
+    }
+
+    /// Writes the interrupt command register and waits until the IPI is sent.
+    /// Both halves are written with interrupts off so that a handler sending an
+    /// IPI of its own cannot slip in between.
+    fn send_ipi(&self, destination: u8, command: u32) {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            self.registers
+                .write32(LAPIC_ICR_HIGH, (destination as u32) << 24);
+            self.registers.write32(LAPIC_ICR_LOW, command);
+
+            while self.registers.read32(LAPIC_ICR_LOW) & ICR_SEND_PENDING != 0 {
+                core::hint::spin_loop();
+            }
+        });
+    }
+}
+
+pub struct IoApic {
+    registers: MmioRegion,
+    id: u8,
+    /// Number of redirection entries, the inputs are GSIs `0..entries`.
+    entries: u32,
+}
+
+impl IoApic {
+    fn new(addr: PhysAddr) -> Result<Self, memory::Error> {
+        let mut io_apic = IoApic {
+            registers: map_mmio(addr, 0x20)?,
+            id: 0,
+            entries: 0,
+        };
+
+        io_apic.id = ((io_apic.read(IOAPIC_ID) >> 24) & 0xf) as u8;
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xff) + 1;
+
+        for gsi in 0..io_apic.entries {
+            io_apic.write_entry(gsi, REDIRECTION_MASKED);
+        }
+
+        Ok(io_apic)
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn entries(&self) -> u32 {
+        self.entries
+    }
+
+    fn read(&self, register: u32) -> u32 {
+        self.registers.write32(IOAPIC_SELECT, register);
+        self.registers.read32(IOAPIC_WINDOW)
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        self.registers.write32(IOAPIC_SELECT, register);
+        self.registers.write32(IOAPIC_WINDOW, value);
+    }
+
+    fn read_entry(&self, gsi: u32) -> u64 {
+        let low = self.read(IOAPIC_REDIRECTION + gsi * 2) as u64;
+        let high = self.read(IOAPIC_REDIRECTION + gsi * 2 + 1) as u64;
+        high << 32 | low
+    }
+
+    fn write_entry(&mut self, gsi: u32, entry: u64) {
+        // Keep the entry masked while it is half written.
+        self.write(IOAPIC_REDIRECTION + gsi * 2, REDIRECTION_MASKED as u32);
+        self.write(IOAPIC_REDIRECTION + gsi * 2 + 1, (entry >> 32) as u32);
+        self.write(IOAPIC_REDIRECTION + gsi * 2, entry as u32);
+    }
+
+    /// Delivers `gsi` as `vector` to the local APIC `destination` in fixed mode.
+    pub fn route(
+        &mut self,
+        gsi: u32,
+        vector: u8,
+        destination: u8,
+        polarity: Polarity,
+        trigger: TriggerMode,
+        masked: bool,
+    ) {
+        assert!(gsi < self.entries, "GSI {} is not on this I/O APIC", gsi);
+
+        let mut entry = vector as u64 | (destination as u64) << 56;
+        if polarity == Polarity::ActiveLow {
+            entry |= REDIRECTION_ACTIVE_LOW;
+        }
+        if trigger == TriggerMode::Level {
+            entry |= REDIRECTION_LEVEL;
+        }
+        if masked {
+            entry |= REDIRECTION_MASKED;
+        }
+
+        self.write_entry(gsi, entry);
+    }
+
+    /// ISA and PCI lines keep the vectors they have behind the 8259.
+    fn route_line(&mut self, line: u8, destination: u8, masked: bool) {
+        if let Some(wiring) = wiring(line).filter(|wiring| wiring.gsi < self.entries) {
+            self.route(
+                wiring.gsi,
+                irq_vector(line),
+                destination,
+                wiring.polarity,
+                wiring.trigger,
+                masked,
+            );
+        }
+    }
+
+    fn set_line_masked(&mut self, line: u8, masked: bool) {
+        let gsi = match wiring(line).filter(|wiring| wiring.gsi < self.entries) {
+            Some(wiring) => wiring.gsi,
+            None => return,
+        };
+        let entry = self.read_entry(gsi);
+
+        self.write_entry(
+            gsi,
+            if masked {
+                entry | REDIRECTION_MASKED
+            } else {
+                entry & !REDIRECTION_MASKED
+            },
+        );
+    }
+}
+
+/// How interrupt `line` reaches the I/O APIC, `None` when its input is taken by an
+/// overridden ISA IRQ.
+fn wiring(line: u8) -> Option<IrqWiring> {
+    let overrides = ISA_OVERRIDES.lock();
+
+    if let Some(wiring) = overrides.get(line as usize).copied().flatten() {
+        return Some(wiring);
+    }
+    if line < 16 {
+        if overrides
+            .iter()
+            .flatten()
+            .any(|wiring| wiring.gsi == line as u32)
+        {
+            return None;
+        }
+        return Some(IrqWiring {
+            gsi: line as u32,
+            polarity: Polarity::ActiveHigh,
+            trigger: TriggerMode::Edge,
+        });
+    }
+
+    // PCI interrupts are level triggered and active low.
+    Some(IrqWiring {
+        gsi: line as u32,
+        polarity: Polarity::ActiveLow,
+        trigger: TriggerMode::Level,
+    })
+}
@@ -0,0 +1,404 @@
+use core::{arch::asm, fmt};
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{
+        Entry, HandlerFunc, InterruptDescriptorTable, InterruptStackFrameValue, PageFaultErrorCode,
+    },
+    VirtAddr,
+};
+
+use crate::{gdt, println, serial::SERIAL1, serial_println, vga_buffer::WRITER};
+
+/// General purpose registers, in the reverse order the entry stubs push them.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Everything on the stack when an entry stub calls [`exception_handler`].
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub registers: Registers,
+    pub vector: u64,
+    /// Zero for exceptions without an error code.
+    pub error_code: u64,
+    pub stack_frame: InterruptStackFrameValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCode {
+    None,
+    Selector,
+    PageFault,
+    Raw,
+}
+
+struct Exception {
+    name: &'static str,
+    mnemonic: &'static str,
+    error_code: ErrorCode,
+    /// Execution resumes after the handler for exceptions that are not fatal.
+    fatal: bool,
+}
+
+const fn exception(
+    name: &'static str,
+    mnemonic: &'static str,
+    error_code: ErrorCode,
+    fatal: bool,
+) -> Exception {
+    Exception {
+        name,
+        mnemonic,
+        error_code,
+        fatal,
+    }
+}
+
+const RESERVED: Exception = exception("RESERVED", "-", ErrorCode::None, true);
+
+/// Architectural exceptions indexed by vector.
+static EXCEPTIONS: [Exception; 32] = [
+    exception("DIVIDE ERROR", "#DE", ErrorCode::None, true),
+    exception("DEBUG", "#DB", ErrorCode::None, false),
+    exception("NON MASKABLE INTERRUPT", "NMI", ErrorCode::None, false),
+    exception("BREAKPOINT", "#BP", ErrorCode::None, false),
+    exception("OVERFLOW", "#OF", ErrorCode::None, true),
+    exception("BOUND RANGE EXCEEDED", "#BR", ErrorCode::None, true),
+    exception("INVALID OPCODE", "#UD", ErrorCode::None, true),
+    exception("DEVICE NOT AVAILABLE", "#NM", ErrorCode::None, true),
+    exception("DOUBLE FAULT", "#DF", ErrorCode::Raw, true),
+    exception("COPROCESSOR SEGMENT OVERRUN", "-", ErrorCode::None, true),
+    exception("INVALID TSS", "#TS", ErrorCode::Selector, true),
+    exception("SEGMENT NOT PRESENT", "#NP", ErrorCode::Selector, true),
+    exception("STACK SEGMENT FAULT", "#SS", ErrorCode::Selector, true),
+    exception("GENERAL PROTECTION FAULT", "#GP", ErrorCode::Selector, true),
+    exception("PAGE FAULT", "#PF", ErrorCode::PageFault, true),
+    RESERVED,
+    exception("X87 FLOATING POINT", "#MF", ErrorCode::None, true),
+    exception("ALIGNMENT CHECK", "#AC", ErrorCode::Raw, true),
+    exception("MACHINE CHECK", "#MC", ErrorCode::None, true),
+    exception("SIMD FLOATING POINT", "#XM", ErrorCode::None, true),
+    exception("VIRTUALIZATION", "#VE", ErrorCode::None, true),
+    exception("CONTROL PROTECTION", "#CP", ErrorCode::Raw, true),
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    RESERVED,
+    exception("HYPERVISOR INJECTION", "#HV", ErrorCode::None, true),
+    exception("VMM COMMUNICATION", "#VC", ErrorCode::Raw, true),
+    exception("SECURITY", "#SX", ErrorCode::Raw, true),
+    RESERVED,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+/// Error code of exceptions caused by a segment selector (#TS, #NP, #SS, #GP).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorErrorCode(pub u64);
+
+impl SelectorErrorCode {
+    /// Set when the exception was raised by an event external to the program.
+    pub fn external(self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    pub fn table(self) -> DescriptorTable {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => DescriptorTable::Gdt,
+            0b10 => DescriptorTable::Ldt,
+            _ => DescriptorTable::Idt,
+        }
+    }
+
+    pub fn index(self) -> u64 {
+        (self.0 >> 3) & 0x1fff
+    }
+}
+
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0 (not segment related)");
+        }
+        write!(
+            f,
+            "{:#x} ({:?} index {}{})",
+            self.0,
+            self.table(),
+            self.index(),
+            if self.external() { ", external" } else { "" }
+        )
+    }
+}
+
+/// Crash report printed for an exception.
+struct Report<'a>(&'a ExceptionFrame);
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frame = self.0;
+        let exception = &EXCEPTIONS[frame.vector as usize];
+        let stack = &frame.stack_frame;
+        let regs = &frame.registers;
+
+        writeln!(
+            f,
+            "EXCEPTION: {} ({}, vector {})",
+            exception.name, exception.mnemonic, frame.vector
+        )?;
+        match exception.error_code {
+            ErrorCode::None => {}
+            ErrorCode::Raw => writeln!(f, "Error code: {:#x}", frame.error_code)?,
+            ErrorCode::Selector => {
+                writeln!(f, "Error code: {}", SelectorErrorCode(frame.error_code))?
+            }
+            ErrorCode::PageFault => {
+                writeln!(
+                    f,
+                    "Error code: {:?}",
+                    PageFaultErrorCode::from_bits_truncate(frame.error_code)
+                )?;
+                writeln!(f, "Address   : {:?}", Cr2::read())?;
+            }
+        }
+
+        writeln!(
+            f,
+            "RIP {:#018x} CS {:#06x} RFLAGS {:#x}",
+            stack.instruction_pointer.as_u64(),
+            stack.code_segment,
+            stack.cpu_flags
+        )?;
+        writeln!(
+            f,
+            "RSP {:#018x} SS {:#06x}",
+            stack.stack_pointer.as_u64(),
+            stack.stack_segment
+        )?;
+
+        let rows = [
+            [("RAX", regs.rax), ("RBX", regs.rbx), ("RCX", regs.rcx)],
+            [("RDX", regs.rdx), ("RSI", regs.rsi), ("RDI", regs.rdi)],
+            [("RBP", regs.rbp), ("R8 ", regs.r8), ("R9 ", regs.r9)],
+            [("R10", regs.r10), ("R11", regs.r11), ("R12", regs.r12)],
+            [("R13", regs.r13), ("R14", regs.r14), ("R15", regs.r15)],
+        ];
+        for (index, row) in rows.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            for (name, value) in row {
+                write!(f, "{} {:#018x}  ", name, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Called by the entry stubs, prints the report on VGA and serial. Returning
+/// resumes the interrupted code, so only exceptions that are not fatal return.
+extern "C" fn exception_handler(frame: &ExceptionFrame) {
+    let exception = &EXCEPTIONS[frame.vector as usize];
+
+    // The exception may have hit while either console was locked.
+    unsafe {
+        WRITER.force_unlock();
+        SERIAL1.force_unlock();
+    }
+
+    let report = Report(frame);
+    println!("{}", report);
+    serial_println!("{}", report);
+
+    if exception.fatal {
+        panic!("EXCEPTION: {}", exception.name);
+    }
+}
+
+/// Pushes the general purpose registers below the vector and error code, calls
+/// [`exception_handler`] and returns from the interrupt if it does.
+macro_rules! handle_exception {
+    () => {
+        concat!(
+            "push rax\n",
+            "push rbx\n",
+            "push rcx\n",
+            "push rdx\n",
+            "push rsi\n",
+            "push rdi\n",
+            "push rbp\n",
+            "push r8\n",
+            "push r9\n",
+            "push r10\n",
+            "push r11\n",
+            "push r12\n",
+            "push r13\n",
+            "push r14\n",
+            "push r15\n",
+            "mov rdi, rsp\n",
+            "cld\n",
+            "call {handler}\n",
+            "pop r15\n",
+            "pop r14\n",
+            "pop r13\n",
+            "pop r12\n",
+            "pop r11\n",
+            "pop r10\n",
+            "pop r9\n",
+            "pop r8\n",
+            "pop rbp\n",
+            "pop rdi\n",
+            "pop rsi\n",
+            "pop rdx\n",
+            "pop rcx\n",
+            "pop rbx\n",
+            "pop rax\n",
+            "add rsp, 16\n",
+            "iretq",
+        )
+    };
+}
+
+/// Entry stub for a vector, exceptions without an error code push a zero in its
+/// place so that every stub builds the same [`ExceptionFrame`].
+macro_rules! exception_stub {
+    ($name:ident, $vector:literal) => {
+        #[naked]
+        unsafe extern "C" fn $name() -> ! {
+            asm!(
+                "push 0",
+                "push {vector}",
+                handle_exception!(),
+                vector = const $vector,
+                handler = sym exception_handler,
+                options(noreturn)
+            )
+        }
+    };
+    ($name:ident, $vector:literal, error_code) => {
+        #[naked]
+        unsafe extern "C" fn $name() -> ! {
+            asm!(
+                "push {vector}",
+                handle_exception!(),
+                vector = const $vector,
+                handler = sym exception_handler,
+                options(noreturn)
+            )
+        }
+    };
+}
+
+exception_stub!(divide_error, 0);
+exception_stub!(debug, 1);
+exception_stub!(non_maskable_interrupt, 2);
+exception_stub!(overflow, 4);
+exception_stub!(bound_range_exceeded, 5);
+exception_stub!(invalid_opcode, 6);
+exception_stub!(device_not_available, 7);
+exception_stub!(double_fault, 8, error_code);
+exception_stub!(invalid_tss, 10, error_code);
+exception_stub!(segment_not_present, 11, error_code);
+exception_stub!(stack_segment_fault, 12, error_code);
+exception_stub!(general_protection_fault, 13, error_code);
+exception_stub!(page_fault, 14, error_code);
+exception_stub!(x87_floating_point, 16);
+exception_stub!(alignment_check, 17, error_code);
+exception_stub!(machine_check, 18);
+exception_stub!(simd_floating_point, 19);
+exception_stub!(virtualization, 20);
+exception_stub!(control_protection, 21, error_code);
+exception_stub!(hypervisor_injection, 28);
+exception_stub!(vmm_communication, 29, error_code);
+exception_stub!(security_exception, 30, error_code);
+
+fn stub_addr(stub: unsafe extern "C" fn() -> !) -> VirtAddr {
+    VirtAddr::new(stub as usize as u64)
+}
+
+/// Entry of an exception that `x86_64` 0.14 still counts as reserved and has no
+/// field for.
+///
+/// # Safety
+/// `vector` must be below 32. The table is `repr(C)` with one entry per vector.
+unsafe fn raw_entry(idt: &mut InterruptDescriptorTable, vector: usize) -> &mut Entry<HandlerFunc> {
+    &mut *(idt as *mut InterruptDescriptorTable as *mut Entry<HandlerFunc>).add(vector)
+}
+
+/// Points every exception entry except the breakpoint at its entry stub.
+pub(super) fn install(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt.divide_error.set_handler_addr(stub_addr(divide_error));
+        idt.debug.set_handler_addr(stub_addr(debug));
+        idt.non_maskable_interrupt
+            .set_handler_addr(stub_addr(non_maskable_interrupt));
+        idt.overflow.set_handler_addr(stub_addr(overflow));
+        idt.bound_range_exceeded
+            .set_handler_addr(stub_addr(bound_range_exceeded));
+        idt.invalid_opcode
+            .set_handler_addr(stub_addr(invalid_opcode));
+        idt.device_not_available
+            .set_handler_addr(stub_addr(device_not_available));
+        idt.double_fault
+            .set_handler_addr(stub_addr(double_fault))
+            .set_stack_index(gdt::DOUBLE_FAULT_INDEX);
+        idt.invalid_tss.set_handler_addr(stub_addr(invalid_tss));
+        idt.segment_not_present
+            .set_handler_addr(stub_addr(segment_not_present));
+        idt.stack_segment_fault
+            .set_handler_addr(stub_addr(stack_segment_fault));
+        idt.general_protection_fault
+            .set_handler_addr(stub_addr(general_protection_fault));
+        idt.page_fault.set_handler_addr(stub_addr(page_fault));
+        idt.x87_floating_point
+            .set_handler_addr(stub_addr(x87_floating_point));
+        idt.alignment_check
+            .set_handler_addr(stub_addr(alignment_check));
+        idt.machine_check.set_handler_addr(stub_addr(machine_check));
+        idt.simd_floating_point
+            .set_handler_addr(stub_addr(simd_floating_point));
+        idt.virtualization
+            .set_handler_addr(stub_addr(virtualization));
+        idt.security_exception
+            .set_handler_addr(stub_addr(security_exception));
+        raw_entry(idt, 21).set_handler_addr(stub_addr(control_protection));
+        raw_entry(idt, 28).set_handler_addr(stub_addr(hypervisor_injection));
+        raw_entry(idt, 29).set_handler_addr(stub_addr(vmm_communication));
+    }
+}
+
+#[test_case]
+fn test_selector_error_code() {
+    let code = SelectorErrorCode(0x2d);
+    assert!(code.external());
+    assert_eq!(code.table(), DescriptorTable::Ldt);
+    assert_eq!(code.index(), 5);
+
+    let code = SelectorErrorCode(0x102);
+    assert!(!code.external());
+    assert_eq!(code.table(), DescriptorTable::Idt);
+    assert_eq!(code.index(), 32);
+}
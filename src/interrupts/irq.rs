@@ -0,0 +1,281 @@
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::{
+    instructions::{interrupts::without_interrupts, port::Port},
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+};
+
+use super::{apic, end_of_interrupt, irq_vector, PICS};
+
+/// Lines wired to an interrupt controller: the 16 ISA IRQs and the PCI inputs
+/// of the I/O APIC.
+pub const LEGACY_LINES: usize = 24;
+/// Lines handed out for message signaled interrupts, which bypass the I/O APIC.
+pub const MSI_LINES: usize = 32;
+/// Lines with a dispatch stub.
+pub const IRQ_LINES: usize = LEGACY_LINES + MSI_LINES;
+/// Lines above the ISA range only exist behind the I/O APIC.
+const PIC_LINES: u8 = 16;
+/// Handlers that can share one line.
+const MAX_SHARED: usize = 4;
+/// Line of the second 8259, it must be unmasked for lines 8 to 15 to get through.
+const PIC_CASCADE: u8 = 2;
+const PIC_1_COMMAND: u16 = 0x20;
+const PIC_2_COMMAND: u16 = 0xa0;
+/// OCW3 making the next read of the command port return the in-service register.
+const PIC_READ_ISR: u8 = 0x0b;
+
+pub const IRQ_TIMER: u8 = 0;
+pub const IRQ_KEYBOARD: u8 = 1;
+pub const IRQ_SERIAL: u8 = 4;
+pub const IRQ_RTC: u8 = 8;
+
+/// Called in interrupt context with the line that fired, so it must not block
+/// or allocate.
+pub type IrqHandler = fn(line: u8) -> IrqResult;
+
+/// Tells the dispatcher whether the device of a handler raised the interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqResult {
+    Handled,
+    NotMine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line has no dispatch stub, or needs the I/O APIC which is not enabled.
+    InvalidLine,
+    /// All handler slots of the line are taken.
+    LineFull,
+    NotRegistered,
+}
+
+type Handlers = [Option<IrqHandler>; MAX_SHARED];
+
+const NO_HANDLERS: Mutex<Handlers> = Mutex::new([None; MAX_SHARED]);
+static HANDLERS: [Mutex<Handlers>; IRQ_LINES] = [NO_HANDLERS; IRQ_LINES];
+
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+static UNHANDLED: [AtomicUsize; IRQ_LINES] = [ZERO; IRQ_LINES];
+
+/// One bit per MSI line, set while the line is allocated.
+static MSI_ALLOCATED: AtomicU32 = AtomicU32::new(0);
+
+/// Adds `handler` to the handlers of `line` and unmasks the line.
+///
+/// Lines are shared, every handler of a line is called on each of its interrupts.
+/// The interrupt is acknowledged after the handlers ran.
+pub fn register_irq(line: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    let handlers = HANDLERS.get(line as usize).ok_or(IrqError::InvalidLine)?;
+    if line >= PIC_LINES && !apic::is_enabled() {
+        return Err(IrqError::InvalidLine);
+    }
+
+    without_interrupts(|| {
+        let mut handlers = handlers.lock();
+        let slot = handlers
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(IrqError::LineFull)?;
+
+        *slot = Some(handler);
+        set_masked(line, false);
+        Ok(())
+    })
+}
+
+/// Removes `handler` from `line`, the line is masked once it has no handler left.
+pub fn unregister_irq(line: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    let handlers = HANDLERS.get(line as usize).ok_or(IrqError::InvalidLine)?;
+
+    without_interrupts(|| {
+        let mut handlers = handlers.lock();
+        let slot = handlers
+            .iter_mut()
+            .find(
+                |slot| matches!(slot, Some(registered) if *registered as usize == handler as usize),
+            )
+            .ok_or(IrqError::NotRegistered)?;
+
+        *slot = None;
+        if handlers.iter().all(Option::is_none) {
+            set_masked(line, true);
+        }
+        Ok(())
+    })
+}
+
+/// Interrupts on `line` that none of its handlers claimed.
+pub fn unhandled_irqs(line: u8) -> usize {
+    UNHANDLED
+        .get(line as usize)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// Reserves a line for a message signaled interrupt, its vector is
+/// [`irq_vector`] of the line.
+pub fn allocate_msi_line() -> Option<u8> {
+    let mut allocated = MSI_ALLOCATED.load(Ordering::Relaxed);
+    loop {
+        let index = allocated.trailing_ones() as usize;
+        if index >= MSI_LINES {
+            return None;
+        }
+
+        match MSI_ALLOCATED.compare_exchange_weak(
+            allocated,
+            allocated | 1 << index,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return Some((LEGACY_LINES + index) as u8),
+            Err(current) => allocated = current,
+        }
+    }
+}
+
+/// Returns a line obtained from [`allocate_msi_line`], its handlers must have
+/// been unregistered.
+pub fn free_msi_line(line: u8) {
+    let index = (line as usize)
+        .checked_sub(LEGACY_LINES)
+        .filter(|&index| index < MSI_LINES)
+        .expect("not an MSI line");
+
+    MSI_ALLOCATED.fetch_and(!(1 << index), Ordering::Relaxed);
+}
+
+pub(super) fn has_handlers(line: u8) -> bool {
+    HANDLERS[line as usize].lock().iter().any(Option::is_some)
+}
+
+fn set_masked(line: u8, masked: bool) {
+    // Message signaled interrupts are masked at the device.
+    if line as usize >= LEGACY_LINES {
+        return;
+    }
+    if apic::is_enabled() {
+        apic::set_irq_masked(line, masked);
+        return;
+    }
+
+    let mut pics = PICS.lock();
+    let mut masks = unsafe { pics.read_masks() };
+    let (pic, bit) = ((line / 8) as usize, line % 8);
+
+    if masked {
+        masks[pic] |= 1 << bit;
+    } else {
+        masks[pic] &= !(1 << bit);
+    }
+    if masks[1] != 0xff {
+        masks[0] &= !(1 << PIC_CASCADE);
+    }
+
+    unsafe { pics.write_masks(masks[0], masks[1]) };
+}
+
+/// In-service registers of both 8259s, the master's in the low byte.
+fn pic_in_service() -> u16 {
+    let _pics = PICS.lock();
+    let mut master = Port::<u8>::new(PIC_1_COMMAND);
+    let mut slave = Port::<u8>::new(PIC_2_COMMAND);
+
+    unsafe {
+        master.write(PIC_READ_ISR);
+        slave.write(PIC_READ_ISR);
+        u16::from(master.read()) | u16::from(slave.read()) << 8
+    }
+}
+
+/// The 8259 raises its lowest priority line, 7 or 15, for a request that went
+/// away before it was acknowledged, without setting the line in service.
+fn is_spurious(line: u8) -> bool {
+    line < PIC_LINES && line % 8 == 7 && !apic::is_enabled() && pic_in_service() & (1 << line) == 0
+}
+
+fn dispatch(line: u8) {
+    if is_spurious(line) {
+        // A spurious IRQ 15 still went through the cascade of the master.
+        if line >= 8 {
+            end_of_interrupt(irq_vector(PIC_CASCADE));
+        }
+        return;
+    }
+
+    // Copied out so that handlers can (un)register from their own interrupt.
+    let handlers = *HANDLERS[line as usize].lock();
+
+    let mut handled = false;
+    for handler in handlers.iter().flatten() {
+        handled |= handler(line) == IrqResult::Handled;
+    }
+    if !handled {
+        UNHANDLED[line as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    end_of_interrupt(irq_vector(line));
+}
+
+macro_rules! irq_stubs {
+    ($($line:literal),*) => {
+        [$({
+            extern "x86-interrupt" fn stub(_stack_frame: InterruptStackFrame) {
+                dispatch($line);
+            }
+            stub
+        }),*]
+    };
+}
+
+static IRQ_STUBS: [extern "x86-interrupt" fn(InterruptStackFrame); IRQ_LINES] = irq_stubs!(
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+    26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49,
+    50, 51, 52, 53, 54, 55
+);
+
+/// Points the vector of every line at its dispatch stub.
+pub(super) fn install(idt: &mut InterruptDescriptorTable) {
+    for (line, stub) in IRQ_STUBS.iter().enumerate() {
+        idt[irq_vector(line as u8) as usize].set_handler_fn(*stub);
+    }
+}
+
+#[test_case]
+fn test_shared_registration() {
+    fn first(_line: u8) -> IrqResult {
+        IrqResult::NotMine
+    }
+    fn second(_line: u8) -> IrqResult {
+        IrqResult::Handled
+    }
+
+    // Line 5 is free on the emulated machines the tests run on.
+    let line = 5;
+    register_irq(line, first).unwrap();
+    register_irq(line, second).unwrap();
+    assert!(has_handlers(line));
+
+    assert_eq!(unregister_irq(line, first), Ok(()));
+    assert_eq!(unregister_irq(line, first), Err(IrqError::NotRegistered));
+    assert_eq!(unregister_irq(line, second), Ok(()));
+    assert!(!has_handlers(line));
+
+    assert_eq!(
+        register_irq(IRQ_LINES as u8, second),
+        Err(IrqError::InvalidLine)
+    );
+}
+
+#[test_case]
+fn test_msi_line_allocation() {
+    let first = allocate_msi_line().unwrap();
+    let second = allocate_msi_line().unwrap();
+    assert!(first as usize >= LEGACY_LINES);
+    assert_ne!(first, second);
+
+    free_msi_line(first);
+    assert_eq!(allocate_msi_line(), Some(first));
+    free_msi_line(first);
+    free_msi_line(second);
+}
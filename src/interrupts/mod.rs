@@ -0,0 +1,124 @@
+pub mod apic;
+pub mod exceptions;
+pub mod irq;
+
+pub use irq::{
+    allocate_msi_line, free_msi_line, register_irq, unregister_irq, IrqError, IrqHandler, IrqResult,
+};
+
+use crate::println;
+use lazy_static::lazy_static;
+use pic8259::ChainedPics;
+use spin::Mutex;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+const PIC_1_OFFSET: u8 = 32;
+const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+const SYSCALL_OFFSET: u8 = 0x80;
+
+pub static PICS: Mutex<ChainedPics> =
+    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        exceptions::install(&mut idt);
+        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        irq::install(&mut idt);
+        idt[InterruptIndex::SysCall.as_usize()].set_handler_fn(syscall_interrupt_handler);
+        idt[InterruptIndex::Wakeup.as_usize()].set_handler_fn(wakeup_interrupt_handler);
+        idt[InterruptIndex::ApicSpurious.as_usize()].set_handler_fn(spurious_interrupt_handler);
+        idt
+    };
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum InterruptIndex {
+    Timer = PIC_1_OFFSET,
+    Keyboard,
+    SysCall = SYSCALL_OFFSET,
+    /// IPI that gets a halted CPU out of `hlt`.
+    Wakeup = 0xf0,
+    ApicSpurious = 0xff,
+}
+
+impl InterruptIndex {
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn as_usize(self) -> usize {
+        usize::from(self.as_u8())
+    }
+}
+
+/// Vector of an interrupt line, the same with the 8259 and the APIC.
+pub const fn irq_vector(line: u8) -> u8 {
+    PIC_1_OFFSET + line
+}
+
+pub fn init_idt() {
+    IDT.load();
+}
+
+/// Remaps the 8259 with every line masked and hooks up the keyboard.
+pub fn init_irqs() {
+    unsafe {
+        let mut pics = PICS.lock();
+        pics.initialize();
+        pics.write_masks(0xff, 0xff);
+    }
+
+    register_irq(irq::IRQ_KEYBOARD, keyboard_handler).expect("Keyboard IRQ unavailable");
+}
+
+/// Acknowledges the interrupt on `vector` at whichever controller delivered it.
+fn end_of_interrupt(vector: u8) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(vector) };
+    }
+}
+
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+}
+
+/// Nothing to do, the CPU that was woken checks for work itself.
+extern "x86-interrupt" fn wakeup_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    apic::end_of_interrupt();
+}
+
+/// Spurious interrupts from the local APIC must not be acknowledged.
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn syscall_interrupt_handler(stack_frame: InterruptStackFrame) {
+    println!("SYSCALL: \n{:#?}", stack_frame);
+}
+
+fn keyboard_handler(_line: u8) -> IrqResult {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+
+    let scancode: u8 = unsafe { port.read() };
+
+    crate::task::keyboard::add_scancode(scancode);
+
+    IrqResult::Handled
+}
+
+#[test_case]
+fn test_interrupt() {
+    x86_64::instructions::interrupts::int3();
+}
+
+#[test_case]
+fn test_syscall_interrupt() {
+    use core::arch::asm;
+    unsafe {
+        x86_64::software_interrupt!(0x80);
+    }
+}
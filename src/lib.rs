@@ -1,21 +1,27 @@
 #![no_std]
 #![no_main]
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
 #![feature(custom_test_frameworks)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 #![feature(asm_const)]
 #![feature(const_mut_refs)]
+#![feature(naked_functions)]
 #![feature(pointer_is_aligned)]
 extern crate alloc;
 
+pub mod acpi;
 pub mod allocator;
 pub mod drivers;
 pub mod gdt;
 pub mod interrupts;
 pub mod memory;
+pub mod power;
 pub mod serial;
+pub mod smp;
 pub mod task;
+pub mod time;
 pub mod vga_buffer;
 
 use bootloader::{entry_point, BootInfo};
@@ -43,9 +49,7 @@ fn validate_read<T: Sized>(addr: &VirtAddr) -> Result<(), ReadError> {
 }
 
 pub(crate) fn phys_to_virt_addr(phys_addr: PhysAddr) -> VirtAddr {
-    let offset = BOOT_INFO.get().unwrap().physical_memory_offset;
-    let virt = offset + phys_addr.as_u64();
-    VirtAddr::new(virt)
+    memory::phys_to_virt(phys_addr)
 }
 
 pub(crate) fn read_virt_addr<'a, T>(addr: &mut VirtAddr) -> Result<&'a mut T, ReadError> {
@@ -72,9 +76,8 @@ where
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
-    unsafe {
-        interrupts::PICS.lock().initialize();
-    }
+    interrupts::init_irqs();
+    time::init();
     x86_64::instructions::interrupts::enable();
 }
 
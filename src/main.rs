@@ -9,9 +9,9 @@ extern crate alloc;
 use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use titan_os::{
-    allocator, memory, println,
-    task::{executor::Executor, keyboard::print_keypresses, Task},
-    BOOT_INFO,
+    acpi, allocator, interrupts, memory, println, smp,
+    task::{self, executor::Executor, keyboard::print_keypresses, Priority, Task},
+    time, BOOT_INFO,
 };
 
 #[cfg(not(test))]
@@ -33,15 +33,29 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     BOOT_INFO.init_once(|| boot_info);
     titan_os::init();
-    let (mut mapper, frame_allocator) = unsafe { memory::init(&boot_info) };
-    allocator::init_heap(&mut mapper, frame_allocator).expect("Heap initialization failed");
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Heap initialization failed");
+    if let Err(error) = acpi::init() {
+        println!("ACPI tables unavailable: {:?}", error);
+    }
+    interrupts::apic::init().expect("APIC initialization failed");
+    time::init_clock_source();
+    if let Err(error) = smp::init() {
+        println!("Application processors not started: {:?}", error);
+    }
     #[cfg(test)]
     test_main();
 
     titan_os::drivers::init();
-    let mut executor = Executor::new();
-    executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(print_keypresses()));
+    let executor = Executor::new();
+    executor.spawn(Task::new(example_task())).detach();
+    executor
+        .spawn(
+            Task::new(print_keypresses())
+                .with_name("keyboard")
+                .with_priority(Priority::BottomHalf),
+        )
+        .detach();
     executor.run();
 }
 
@@ -50,6 +64,8 @@ async fn example_number() -> u32 {
 }
 
 async fn example_task() {
-    let number = example_number().await;
-    println!("async number{}", number);
+    let child = task::spawner().spawn(Task::new(example_number()));
+    if let Ok(number) = child.await {
+        println!("async number{}", number);
+    }
 }
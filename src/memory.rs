@@ -1,95 +0,0 @@
-use bootloader::{
-    bootinfo::{MemoryMap, MemoryRegionType},
-    BootInfo,
-};
-use conquer_once::spin::OnceCell;
-use spin::Mutex;
-use x86_64::{
-    structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PhysFrame, Size4KiB,
-    },
-    PhysAddr, VirtAddr,
-};
-
-pub static FRAME_ALLOCATOR: OnceCell<Mutex<BootInfoFrameAllocator>> = OnceCell::uninit();
-
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
-    next: usize,
-}
-
-impl BootInfoFrameAllocator {
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
-            next: 0,
-        }
-    }
-
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        let regions = self.memory_map.iter();
-
-        let usable_regions = regions.filter(|r| r.region_type == MemoryRegionType::Usable);
-
-        let addr_ranges = usable_regions.map(|r| r.range.start_addr()..r.range.end_addr());
-
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
-
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
-}
-
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
-    }
-}
-
-pub struct EmptyFrameAllocator;
-
-unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        None
-    }
-}
-
-pub fn example_mapping(
-    page: Page,
-    mapper: &mut OffsetPageTable,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-) {
-    use x86_64::structures::paging::PageTableFlags as Flags;
-
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::WRITABLE | Flags::PRESENT;
-
-    let map_to_result = unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
-
-    map_to_result.expect("map_to failed").flush();
-}
-
-pub unsafe fn init(
-    boot_info: &'static BootInfo,
-) -> (OffsetPageTable<'static>, BootInfoFrameAllocator) {
-    let page_offset_address = VirtAddr::new(boot_info.physical_memory_offset);
-    let active_level_4_page_table = active_level_4_table(page_offset_address);
-    (
-        OffsetPageTable::new(active_level_4_page_table, page_offset_address),
-        BootInfoFrameAllocator::init(&boot_info.memory_map),
-    )
-}
-
-unsafe fn active_level_4_table(page_offset_address: VirtAddr) -> &'static mut PageTable {
-    use x86_64::registers::control::Cr3;
-
-    let (level_4_address, _) = Cr3::read();
-
-    let phys = level_4_address.start_address();
-
-    let virt = page_offset_address + phys.as_u64();
-    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
-
-    &mut *page_table_ptr
-}
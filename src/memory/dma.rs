@@ -0,0 +1,186 @@
+use core::{
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+use x86_64::{
+    structures::paging::{frame::PhysFrameRange, page::PageRange, PageTableFlags},
+    PhysAddr, VirtAddr,
+};
+
+use super::{map_range, unmap_range, virt::KERNEL_SPACE, Error, FRAME_ALLOCATOR, FRAME_SIZE};
+
+const DMA_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Physically contiguous, zeroed memory mapped uncached into kernel space.
+///
+/// The frames, the mapping and the virtual range are released on drop, so the
+/// device must be done with the memory before the region goes away.
+pub struct DmaRegion {
+    frames: PhysFrameRange,
+    pages: PageRange,
+    size: usize,
+}
+
+impl DmaRegion {
+    pub fn new(size: usize) -> Result<Self, Error> {
+        Self::with_alignment(size, FRAME_SIZE as usize)
+    }
+
+    /// Allocates a region whose physical address is a multiple of `align`.
+    pub fn with_alignment(size: usize, align: usize) -> Result<Self, Error> {
+        assert!(align.is_power_of_two(), "Alignment must be a power of 2");
+
+        let count = (size.max(1) as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+        let align_frames = (align as u64 / FRAME_SIZE).max(1) as usize;
+
+        let pages = KERNEL_SPACE
+            .lock()
+            .allocate(count)
+            .ok_or(Error::OutOfVirtualSpace)?;
+
+        let frames = FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .allocate_contiguous(count as usize, align_frames);
+
+        let frames = match frames {
+            Some(frames) => frames,
+            None => {
+                KERNEL_SPACE.lock().release(pages);
+                return Err(Error::OutOfFrames);
+            }
+        };
+
+        // From here on dropping the region undoes whatever has been set up.
+        let region = Self {
+            frames,
+            pages,
+            size,
+        };
+
+        unsafe {
+            map_range(pages, frames, DMA_FLAGS)?;
+            core::ptr::write_bytes(region.as_mut_ptr(), 0, (count * FRAME_SIZE) as usize);
+        }
+
+        Ok(region)
+    }
+
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.frames.start.start_address()
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.pages.start.start_address()
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.virt_addr().as_mut_ptr()
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        unsafe {
+            unmap_range(self.pages);
+            FRAME_ALLOCATOR
+                .get()
+                .unwrap()
+                .lock()
+                .deallocate_contiguous(self.frames);
+        }
+        KERNEL_SPACE.lock().release(self.pages);
+    }
+}
+
+/// A typed value living in a [`DmaRegion`], e.g. a descriptor ring.
+pub struct DmaBuffer<T: ?Sized> {
+    region: DmaRegion,
+    ptr: NonNull<T>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for DmaBuffer<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for DmaBuffer<T> {}
+
+impl<T> DmaBuffer<T> {
+    pub fn new(value: T) -> Result<Self, Error> {
+        let region = DmaRegion::with_alignment(mem::size_of::<T>(), mem::align_of::<T>())?;
+        let ptr = region.as_mut_ptr() as *mut T;
+
+        unsafe { ptr.write(value) };
+
+        Ok(Self {
+            region,
+            ptr: NonNull::new(ptr).unwrap(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Default> DmaBuffer<[T]> {
+    pub fn new_slice(len: usize) -> Result<Self, Error> {
+        let size = mem::size_of::<T>()
+            .checked_mul(len)
+            .ok_or(Error::InvalidRegion)?;
+        let region = DmaRegion::with_alignment(size, mem::align_of::<T>())?;
+        let data = region.as_mut_ptr() as *mut T;
+
+        for i in 0..len {
+            unsafe { data.add(i).write(T::default()) };
+        }
+
+        let ptr = core::ptr::slice_from_raw_parts_mut(data, len);
+
+        Ok(Self {
+            region,
+            ptr: NonNull::new(ptr).unwrap(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: ?Sized> DmaBuffer<T> {
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.region.phys_addr()
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.region.virt_addr()
+    }
+}
+
+impl<T: ?Sized> Deref for DmaBuffer<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for DmaBuffer<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized> Drop for DmaBuffer<T> {
+    fn drop(&mut self) {
+        unsafe { core::ptr::drop_in_place(self.ptr.as_ptr()) };
+    }
+}
@@ -0,0 +1,383 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::alloc::Layout;
+use x86_64::{
+    structures::paging::{
+        frame::PhysFrameRange, FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::allocator::buddy::BuddyAllocator;
+
+pub const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+/// Frames below 1 MiB, only handed out by [`BitmapFrameAllocator::allocate_frame_below`]
+/// so that some are left for real mode code.
+const LOW_MEMORY_FRAMES: usize = 256;
+const LOW_MEMORY_WORDS: usize = LOW_MEMORY_FRAMES / BITS_PER_WORD;
+/// Separate ranges of frames a [`BuddyFrameAllocator`] can be given.
+const MAX_BUDDY_REGIONS: usize = 32;
+
+/// Physical frame allocator keeping one bit per 4 KiB frame, a set bit marks the frame as used.
+///
+/// The bitmap is stored in the first usable region large enough to hold it and is
+/// accessed through the physical memory mapping set up by the bootloader.
+pub struct BitmapFrameAllocator {
+    memory_map: &'static MemoryMap,
+    bitmap: &'static mut [u64],
+    /// First frame of the bitmap and the number of frames it spans.
+    bitmap_frames: (usize, usize),
+    total_frames: usize,
+    free_frames: usize,
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Builds the bitmap from the usable regions of the memory map.
+    ///
+    /// # Safety
+    /// The caller must guarantee that the memory map is valid and that the whole
+    /// physical memory is mapped at `physical_memory_offset`.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let max_addr = usable_regions()
+            .map(|r| r.range.end_addr())
+            .max()
+            .unwrap_or(0);
+        let frame_count = (max_addr / FRAME_SIZE) as usize;
+        let words = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_frames = ((words * 8) as u64 + FRAME_SIZE - 1) / FRAME_SIZE;
+
+        let bitmap_start = usable_regions()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_frames * FRAME_SIZE)
+            .map(|r| r.range.start_addr())
+            .expect("No usable region large enough for the frame bitmap");
+
+        let bitmap_ptr = (physical_memory_offset + bitmap_start).as_mut_ptr::<u64>();
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_ptr, words);
+        bitmap.fill(u64::MAX);
+
+        let bitmap_index = (bitmap_start / FRAME_SIZE) as usize;
+        let mut allocator = BitmapFrameAllocator {
+            memory_map,
+            bitmap,
+            bitmap_frames: (bitmap_index, bitmap_frames as usize),
+            total_frames: 0,
+            free_frames: 0,
+            next: LOW_MEMORY_WORDS,
+        };
+
+        for region in usable_regions() {
+            let start = (region.range.start_addr() / FRAME_SIZE) as usize;
+            let end = (region.range.end_addr() / FRAME_SIZE) as usize;
+            for index in start..end {
+                allocator.mark_free(index);
+            }
+            allocator.total_frames += end - start;
+        }
+
+        for index in bitmap_index..bitmap_index + bitmap_frames as usize {
+            allocator.mark_used(index);
+        }
+
+        allocator
+    }
+
+    /// Number of frames handed to the allocator by the memory map.
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.total_frames - self.free_frames
+    }
+
+    /// Allocates `count` physically contiguous frames whose first frame number is a
+    /// multiple of `align` frames.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrameRange> {
+        assert!(count > 0, "Cannot allocate zero frames");
+        assert!(align.is_power_of_two(), "Alignment must be a power of 2");
+
+        let frame_count = self.bitmap.len() * BITS_PER_WORD;
+        let mut start = LOW_MEMORY_FRAMES;
+
+        while start + count <= frame_count {
+            match (start..start + count)
+                .rev()
+                .find(|&index| self.is_used(index))
+            {
+                Some(used) => start = (used + align) & !(align - 1),
+                None => {
+                    for index in start..start + count {
+                        self.mark_used(index);
+                    }
+                    let first = frame_at(start);
+                    return Some(PhysFrame::range(first, first + count as u64));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Allocates a frame below `limit`, for code and data that must be reachable
+    /// in real mode. Frame 0 holds the real mode interrupt table and is skipped.
+    pub fn allocate_frame_below(&mut self, limit: PhysAddr) -> Option<PhysFrame> {
+        let end = ((limit.as_u64() / FRAME_SIZE) as usize).min(self.bitmap.len() * BITS_PER_WORD);
+        let index = (1..end).find(|&index| !self.is_used(index))?;
+
+        self.mark_used(index);
+        Some(frame_at(index))
+    }
+
+    /// Returns a range obtained from [`allocate_contiguous`](Self::allocate_contiguous).
+    ///
+    /// # Safety
+    /// The frames must no longer be in use.
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        for frame in range {
+            self.deallocate_frame(frame);
+        }
+    }
+
+    /// Whether the frame lies in a usable region of the memory map and is not
+    /// part of the bitmap itself, so that it may ever be handed out.
+    fn is_managed(&self, index: usize) -> bool {
+        let (bitmap_start, bitmap_len) = self.bitmap_frames;
+        let addr = index as u64 * FRAME_SIZE;
+
+        index < self.bitmap.len() * BITS_PER_WORD
+            && !(bitmap_start..bitmap_start + bitmap_len).contains(&index)
+            && self.memory_map.iter().any(|region| {
+                region.region_type == MemoryRegionType::Usable
+                    && (region.range.start_addr()..region.range.end_addr()).contains(&addr)
+            })
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn mark_used(&mut self, index: usize) {
+        if !self.is_used(index) {
+            self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+            self.free_frames -= 1;
+        }
+    }
+
+    fn mark_free(&mut self, index: usize) {
+        if self.is_used(index) {
+            self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+            self.free_frames += 1;
+        }
+    }
+}
+
+fn frame_at(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let words = self.bitmap.len();
+
+        for offset in 0..words {
+            let word = (self.next + offset) % words;
+            if word >= LOW_MEMORY_WORDS && self.bitmap[word] != u64::MAX {
+                let index = word * BITS_PER_WORD + self.bitmap[word].trailing_ones() as usize;
+                self.mark_used(index);
+                self.next = word;
+                return Some(frame_at(index));
+            }
+        }
+
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+
+        assert!(
+            self.is_managed(index),
+            "Freeing frame {:?} that is not usable memory",
+            frame
+        );
+        assert!(self.is_used(index), "Double free of frame {:?}", frame);
+
+        self.mark_free(index);
+        self.next = self.next.min(index / BITS_PER_WORD).max(LOW_MEMORY_WORDS);
+    }
+}
+
+/// Frame allocator backed by a [`BuddyAllocator`], whose free lists live in the free
+/// frames themselves and are reached through the physical memory mapping.
+///
+/// Contiguous allocations are rounded up to a power of two internally and the
+/// unused tail is returned immediately.
+pub struct BuddyFrameAllocator {
+    buddy: BuddyAllocator,
+    physical_memory_offset: VirtAddr,
+    /// Frames handed to the allocator, adjacent ranges merged.
+    regions: [Option<PhysFrameRange>; MAX_BUDDY_REGIONS],
+}
+
+impl BuddyFrameAllocator {
+    pub fn new(physical_memory_offset: VirtAddr) -> Self {
+        BuddyFrameAllocator {
+            buddy: BuddyAllocator::new(),
+            physical_memory_offset,
+            regions: [None; MAX_BUDDY_REGIONS],
+        }
+    }
+
+    /// Creates an allocator managing all usable regions of the memory map.
+    ///
+    /// # Safety
+    /// Same requirements as [`BitmapFrameAllocator::init`].
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let mut allocator = Self::new(physical_memory_offset);
+
+        for region in memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+        {
+            let start = PhysFrame::containing_address(PhysAddr::new(region.range.start_addr()));
+            let end = PhysFrame::containing_address(PhysAddr::new(region.range.end_addr()));
+            allocator.add_frames(PhysFrame::range(start, end));
+        }
+
+        allocator
+    }
+
+    /// Hands unused frames to the allocator.
+    ///
+    /// # Safety
+    /// The frames must be unused and mapped at the physical memory offset.
+    ///
+    /// # Panics
+    /// When the frames are not adjacent to a range added before and the allocator
+    /// already tracks as many separate ranges as it can.
+    pub unsafe fn add_frames(&mut self, range: PhysFrameRange) {
+        if range.is_empty() {
+            return;
+        }
+        self.record_region(range);
+
+        let start = self.physical_memory_offset + range.start.start_address().as_u64();
+        let size = (range.end.start_address() - range.start.start_address()) as usize;
+        self.buddy.add_region(start.as_u64() as usize, size);
+    }
+
+    fn record_region(&mut self, range: PhysFrameRange) {
+        for region in self.regions.iter_mut().flatten() {
+            if region.end == range.start {
+                region.end = range.end;
+                return;
+            }
+            if range.end == region.start {
+                region.start = range.start;
+                return;
+            }
+        }
+
+        let slot = self
+            .regions
+            .iter_mut()
+            .find(|region| region.is_none())
+            .expect("Too many separate frame ranges for the buddy frame allocator");
+        *slot = Some(range);
+    }
+
+    /// Whether `range` lies in the frames given to the allocator.
+    fn is_managed(&self, range: PhysFrameRange) -> bool {
+        self.regions
+            .iter()
+            .flatten()
+            .any(|region| region.start <= range.start && range.end <= region.end)
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.buddy.heap_size() / FRAME_SIZE as usize
+    }
+
+    pub fn free_frames(&self) -> usize {
+        (self.buddy.heap_size() - self.buddy.allocated()) / FRAME_SIZE as usize
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.buddy.allocated() / FRAME_SIZE as usize
+    }
+
+    /// Allocates `count` physically contiguous frames whose first frame number is a
+    /// multiple of `align` frames.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrameRange> {
+        assert!(count > 0, "Cannot allocate zero frames");
+        assert!(align.is_power_of_two(), "Alignment must be a power of 2");
+
+        let frame_size = FRAME_SIZE as usize;
+        let block_size = count.next_power_of_two().max(align) * frame_size;
+        let layout = Layout::from_size_align(block_size, block_size).ok()?;
+
+        let start = self.buddy.allocate(layout) as usize;
+        if start == 0 {
+            return None;
+        }
+
+        let used = count * frame_size;
+        if block_size > used {
+            unsafe { self.buddy.release(start + used, block_size - used) };
+        }
+
+        let first = PhysFrame::containing_address(self.phys_addr(start));
+        Some(PhysFrame::range(first, first + count as u64))
+    }
+
+    /// Returns a range obtained from [`allocate_contiguous`](Self::allocate_contiguous).
+    ///
+    /// # Safety
+    /// The frames must no longer be in use.
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        let start = self.physical_memory_offset + range.start.start_address().as_u64();
+        let size = (range.end.start_address() - range.start.start_address()) as usize;
+
+        assert!(
+            self.is_managed(range),
+            "Freeing frames {:?} that the allocator does not manage",
+            range
+        );
+        assert!(
+            !self.buddy.overlaps_free(start.as_u64() as usize, size),
+            "Double free of frames in {:?}",
+            range
+        );
+
+        self.buddy.release(start.as_u64() as usize, size);
+    }
+
+    fn phys_addr(&self, addr: usize) -> PhysAddr {
+        PhysAddr::new(addr as u64 - self.physical_memory_offset.as_u64())
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate_contiguous(1, 1).map(|range| range.start)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.deallocate_contiguous(PhysFrame::range(frame, frame + 1));
+    }
+}
@@ -0,0 +1,116 @@
+use core::mem;
+use x86_64::{
+    structures::paging::{page::PageRange, PageTableFlags, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+use super::{map_range, unmap_range, virt::KERNEL_SPACE, Error, FRAME_SIZE};
+
+const MMIO_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_EXECUTE);
+
+/// Device registers mapped uncached into kernel space.
+///
+/// Offsets passed to the accessors are relative to the physical address given
+/// to [`map_mmio`] and are checked against the size of the region.
+pub struct MmioRegion {
+    pages: PageRange,
+    base: VirtAddr,
+    size: usize,
+}
+
+/// Maps `size` bytes of device memory starting at `phys`.
+pub fn map_mmio(phys: PhysAddr, size: usize) -> Result<MmioRegion, Error> {
+    if size == 0 {
+        return Err(Error::InvalidRegion);
+    }
+
+    let end = phys
+        .as_u64()
+        .checked_add(size as u64 - 1)
+        .ok_or(Error::InvalidRegion)?;
+    let first = PhysFrame::containing_address(phys);
+    let last =
+        PhysFrame::containing_address(PhysAddr::try_new(end).map_err(|_| Error::InvalidRegion)?);
+    let frames = PhysFrame::range(first, last + 1);
+    let count = (last.start_address() - first.start_address()) / FRAME_SIZE + 1;
+
+    let pages = KERNEL_SPACE
+        .lock()
+        .allocate(count)
+        .ok_or(Error::OutOfVirtualSpace)?;
+
+    // From here on dropping the region undoes whatever has been set up.
+    let region = MmioRegion {
+        pages,
+        base: pages.start.start_address() + (phys - first.start_address()),
+        size,
+    };
+
+    unsafe { map_range(pages, frames, MMIO_FLAGS)? };
+
+    Ok(region)
+}
+
+impl MmioRegion {
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Volatile read of a `T` at `offset` bytes into the region.
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        unsafe { core::ptr::read_volatile(self.ptr::<T>(offset)) }
+    }
+
+    /// Volatile write of a `T` at `offset` bytes into the region.
+    pub fn write<T: Copy>(&self, offset: usize, value: T) {
+        unsafe { core::ptr::write_volatile(self.ptr::<T>(offset), value) }
+    }
+
+    pub fn read32(&self, offset: usize) -> u32 {
+        self.read(offset)
+    }
+
+    pub fn write32(&self, offset: usize, value: u32) {
+        self.write(offset, value)
+    }
+
+    pub fn read64(&self, offset: usize) -> u64 {
+        self.read(offset)
+    }
+
+    pub fn write64(&self, offset: usize, value: u64) {
+        self.write(offset, value)
+    }
+
+    fn ptr<T>(&self, offset: usize) -> *mut T {
+        assert!(
+            offset + mem::size_of::<T>() <= self.size,
+            "MMIO access at {:#x} outside of region of size {:#x}",
+            offset,
+            self.size
+        );
+
+        let ptr = (self.base + offset).as_mut_ptr::<T>();
+        assert!(ptr.is_aligned(), "Unaligned MMIO access at {:#x}", offset);
+        ptr
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        unsafe { unmap_range(self.pages) };
+        KERNEL_SPACE.lock().release(self.pages);
+    }
+}
@@ -0,0 +1,145 @@
+mod dma;
+mod frame;
+mod mmio;
+mod virt;
+
+pub use dma::{DmaBuffer, DmaRegion};
+pub use frame::{BitmapFrameAllocator, BuddyFrameAllocator, FRAME_SIZE};
+pub use mmio::{map_mmio, MmioRegion};
+pub use virt::{KERNEL_SPACE_SIZE, KERNEL_SPACE_START};
+
+use bootloader::BootInfo;
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{
+        frame::PhysFrameRange, page::PageRange, FrameAllocator, Mapper, OffsetPageTable, Page,
+        PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+pub static FRAME_ALLOCATOR: OnceCell<Mutex<BitmapFrameAllocator>> = OnceCell::uninit();
+pub static MAPPER: OnceCell<Mutex<OffsetPageTable<'static>>> = OnceCell::uninit();
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+
+#[derive(Debug, Clone, Copy)]
+pub enum Error {
+    OutOfFrames,
+    OutOfVirtualSpace,
+    MapFailed,
+    InvalidRegion,
+}
+
+pub struct EmptyFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        None
+    }
+}
+
+pub fn example_mapping(
+    page: Page,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    let flags = Flags::WRITABLE | Flags::PRESENT;
+
+    let map_to_result = unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
+
+    map_to_result.expect("map_to failed").flush();
+}
+
+pub unsafe fn init(boot_info: &'static BootInfo) {
+    let page_offset_address = VirtAddr::new(boot_info.physical_memory_offset);
+    let active_level_4_page_table = active_level_4_table(page_offset_address);
+    let mapper = OffsetPageTable::new(active_level_4_page_table, page_offset_address);
+    let frame_allocator = BitmapFrameAllocator::init(&boot_info.memory_map, page_offset_address);
+
+    PHYSICAL_MEMORY_OFFSET.init_once(|| page_offset_address);
+    MAPPER.init_once(|| Mutex::new(mapper));
+    FRAME_ALLOCATOR.init_once(|| Mutex::new(frame_allocator));
+}
+
+/// Address of `phys` inside the bootloader's mapping of the whole physical memory.
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    *PHYSICAL_MEMORY_OFFSET.get().unwrap() + phys.as_u64()
+}
+
+/// Inverse of [`phys_to_virt`], only valid for addresses inside that mapping.
+pub fn virt_to_phys(virt: VirtAddr) -> PhysAddr {
+    PhysAddr::new(virt - *PHYSICAL_MEMORY_OFFSET.get().unwrap())
+}
+
+/// Maps `pages` onto `frames` one to one in the kernel page table.
+///
+/// Locks `MAPPER` and then `FRAME_ALLOCATOR`, so neither may be held by the caller.
+unsafe fn map_range(
+    pages: PageRange,
+    frames: PhysFrameRange,
+    flags: PageTableFlags,
+) -> Result<(), Error> {
+    let mut mapper = MAPPER.get().unwrap().lock();
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+
+    for (page, frame) in pages.zip(frames) {
+        mapper
+            .map_to(page, frame, flags, &mut *frame_allocator)
+            .map_err(|_| Error::MapFailed)?
+            .flush();
+    }
+
+    Ok(())
+}
+
+/// Maps `frame` at the virtual address equal to its physical address, for code
+/// that runs while paging is being switched on. A page that already maps to
+/// `frame` is left as it is.
+///
+/// Locks `MAPPER` and then `FRAME_ALLOCATOR`, so neither may be held by the caller.
+pub unsafe fn identity_map(frame: PhysFrame, flags: PageTableFlags) -> Result<(), Error> {
+    let address = frame.start_address();
+    let page = Page::containing_address(VirtAddr::new(address.as_u64()));
+    let mut mapper = MAPPER.get().unwrap().lock();
+
+    match mapper.translate_addr(page.start_address()) {
+        Some(mapped) if mapped == address => return Ok(()),
+        Some(_) => return Err(Error::MapFailed),
+        None => {}
+    }
+
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    mapper
+        .map_to(page, frame, flags, &mut *frame_allocator)
+        .map_err(|_| Error::MapFailed)?
+        .flush();
+    Ok(())
+}
+
+/// Unmaps `pages`, skipping the ones that are not mapped.
+unsafe fn unmap_range(pages: PageRange) {
+    let mut mapper = MAPPER.get().unwrap().lock();
+
+    for page in pages {
+        if let Ok((_, flush)) = mapper.unmap(page) {
+            flush.flush();
+        }
+    }
+}
+
+unsafe fn active_level_4_table(page_offset_address: VirtAddr) -> &'static mut PageTable {
+    use x86_64::registers::control::Cr3;
+
+    let (level_4_address, _) = Cr3::read();
+
+    let phys = level_4_address.start_address();
+
+    let virt = page_offset_address + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr
+}
@@ -0,0 +1,65 @@
+use alloc::collections::BTreeMap;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{page::PageRange, Page},
+    VirtAddr,
+};
+
+use super::FRAME_SIZE;
+
+pub const KERNEL_SPACE_START: u64 = 0x_5555_0000_0000;
+pub const KERNEL_SPACE_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+lazy_static! {
+    pub(super) static ref KERNEL_SPACE: Mutex<VirtualSpace> =
+        Mutex::new(VirtualSpace::new(KERNEL_SPACE_START, KERNEL_SPACE_SIZE));
+}
+
+/// First fit allocator for page aligned ranges of kernel virtual address space.
+pub(super) struct VirtualSpace {
+    /// Free ranges, keyed by start address with the exclusive end address as value.
+    free: BTreeMap<u64, u64>,
+}
+
+impl VirtualSpace {
+    fn new(start: u64, size: u64) -> Self {
+        let mut free = BTreeMap::new();
+        free.insert(start, start + size);
+        Self { free }
+    }
+
+    pub fn allocate(&mut self, pages: u64) -> Option<PageRange> {
+        let size = pages * FRAME_SIZE;
+        let (&start, &end) = self
+            .free
+            .iter()
+            .find(|(start, end)| **end - **start >= size)?;
+
+        self.free.remove(&start);
+        if end - start > size {
+            self.free.insert(start + size, end);
+        }
+
+        let first = Page::containing_address(VirtAddr::new(start));
+        Some(Page::range(first, first + pages))
+    }
+
+    pub fn release(&mut self, range: PageRange) {
+        let mut start = range.start.start_address().as_u64();
+        let mut end = range.end.start_address().as_u64();
+
+        if let Some((&prev_start, &prev_end)) = self.free.range(..start).next_back() {
+            if prev_end == start {
+                self.free.remove(&prev_start);
+                start = prev_start;
+            }
+        }
+
+        if let Some(next_end) = self.free.remove(&end) {
+            end = next_end;
+        }
+
+        self.free.insert(start, end);
+    }
+}
@@ -0,0 +1,141 @@
+//! Turning the machine off and restarting it.
+
+use x86_64::{
+    instructions::{interrupts, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    acpi::{self, Fadt, GenericAddress},
+    hlt_loop, memory, println, time,
+};
+
+/// Enables sleeping through the PM1 control registers.
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+/// Set in PM1 control once the firmware handed power management to the OS.
+const SCI_EN: u16 = 1 << 0;
+
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64;
+const KEYBOARD_CONTROLLER_COMMAND: u16 = 0x64;
+const KEYBOARD_INPUT_FULL: u8 = 1 << 1;
+/// Pulses the CPU reset line.
+const KEYBOARD_RESET: u8 = 0xfe;
+
+/// Ports that power off emulators without going through the ACPI tables.
+const EMULATOR_SHUTDOWN: [(u16, u16); 3] = [
+    // QEMU.
+    (0x604, 0x2000),
+    // Bochs and older QEMU.
+    (0xb004, 0x2000),
+    // VirtualBox.
+    (0x4004, 0x3400),
+];
+
+/// How long a reset method gets before the next one is tried.
+const RESET_TIMEOUT_MICROS: u64 = 50_000;
+
+/// Turns the machine off, through ACPI when the `\_S5` sleep type can be found.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+
+    if let Some(fadt) = Fadt::get() {
+        acpi_shutdown(fadt);
+    }
+    for (port, value) in EMULATOR_SHUTDOWN {
+        unsafe { Port::new(port).write(value) };
+    }
+
+    println!("Shutdown failed, it is now safe to turn off the machine");
+    hlt_loop();
+}
+
+fn acpi_shutdown(fadt: &Fadt) {
+    let (sleep_type, pm1a) = match (acpi::sleep_type(b"_S5_"), fadt.pm1a_control_port()) {
+        (Some(sleep_type), Some(pm1a)) => (sleep_type, pm1a),
+        _ => return,
+    };
+
+    enable_acpi_mode(fadt, pm1a);
+
+    unsafe {
+        Port::new(pm1a).write((sleep_type.a as u16) << SLP_TYP_SHIFT | SLP_EN);
+        if let Some(pm1b) = fadt.pm1b_control_port() {
+            Port::new(pm1b).write((sleep_type.b as u16) << SLP_TYP_SHIFT | SLP_EN);
+        }
+    }
+    time::udelay(RESET_TIMEOUT_MICROS);
+}
+
+/// Asks the firmware to hand over power management, if it has not already.
+fn enable_acpi_mode(fadt: &Fadt, pm1a: u16) {
+    let mut control: Port<u16> = Port::new(pm1a);
+    let (smi_command, acpi_enable) = (fadt.smi_command_port, fadt.acpi_enable);
+    if unsafe { control.read() } & SCI_EN != 0 || smi_command == 0 || acpi_enable == 0 {
+        return;
+    }
+
+    unsafe { Port::new(smi_command as u16).write(acpi_enable) };
+    for _ in 0..300 {
+        if unsafe { control.read() } & SCI_EN != 0 {
+            break;
+        }
+        time::udelay(1000);
+    }
+}
+
+/// Restarts the machine, trying the ACPI reset register, then the keyboard
+/// controller and finally a triple fault.
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    if let Some((register, value)) = Fadt::get().and_then(Fadt::reset_register) {
+        write_reset_register(register, value);
+        time::udelay(RESET_TIMEOUT_MICROS);
+    }
+
+    keyboard_controller_reset();
+    time::udelay(RESET_TIMEOUT_MICROS);
+
+    triple_fault();
+}
+
+fn write_reset_register(register: GenericAddress, value: u8) {
+    let address = register.address;
+    match register.address_space {
+        GenericAddress::SYSTEM_IO => unsafe { Port::new(address as u16).write(value) },
+        GenericAddress::SYSTEM_MEMORY => {
+            if let Ok(region) = memory::map_mmio(PhysAddr::new(address), 1) {
+                region.write::<u8>(0, value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn keyboard_controller_reset() {
+    let mut status: Port<u8> = Port::new(KEYBOARD_CONTROLLER_STATUS);
+    let mut command: Port<u8> = Port::new(KEYBOARD_CONTROLLER_COMMAND);
+
+    for _ in 0..1000 {
+        if unsafe { status.read() } & KEYBOARD_INPUT_FULL == 0 {
+            break;
+        }
+        time::udelay(10);
+    }
+    unsafe { command.write(KEYBOARD_RESET) };
+}
+
+/// With an empty IDT the breakpoint cannot be delivered, nor can the double
+/// fault that follows, and the CPU resets.
+fn triple_fault() -> ! {
+    let idt = DescriptorTablePointer {
+        limit: 0,
+        base: VirtAddr::zero(),
+    };
+
+    unsafe { lidt(&idt) };
+    interrupts::int3();
+    hlt_loop();
+}
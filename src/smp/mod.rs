@@ -0,0 +1,198 @@
+//! Bringing up the application processors listed in the MADT.
+//!
+//! Processors are started one at a time with INIT and startup IPIs through a
+//! trampoline in low memory. Each gets a stack, a GDT and TSS and per-CPU data
+//! of its own, then runs the executor once the bootstrap processor created it.
+
+pub mod percpu;
+mod trampoline;
+
+pub use percpu::{cpu_id, PerCpu};
+
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use self::trampoline::Trampoline;
+use crate::{
+    acpi::Madt,
+    gdt,
+    interrupts::{self, apic, InterruptIndex},
+    memory::{self, FRAME_ALLOCATOR, FRAME_SIZE},
+    println, task, time,
+};
+
+/// CPUs that are brought online, the rest are left halted.
+pub const MAX_CPUS: usize = 64;
+const AP_STACK_FRAMES: usize = 16;
+
+const INIT_DELAY_MICROS: u64 = 10_000;
+const STARTUP_DELAY_MICROS: u64 = 200;
+const START_TIMEOUT_MICROS: u64 = 100_000;
+
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+/// Index the next processor to come online takes.
+static NEXT_CPU: AtomicUsize = AtomicUsize::new(1);
+/// Progress of the processor being started, one of the states below.
+static START_STATE: AtomicU8 = AtomicU8::new(WAITING);
+
+const WAITING: u8 = 0;
+/// The processor reached [`ap_main`] and is done with the trampoline.
+const CLAIMED: u8 = 1;
+/// The processor is online.
+const STARTED: u8 = 2;
+/// The bootstrap processor gave up, a processor reaching [`ap_main`] now halts.
+const ABANDONED: u8 = 3;
+static APIC_IDS: [AtomicU8; MAX_CPUS] = {
+    const UNKNOWN: AtomicU8 = AtomicU8::new(0);
+    [UNKNOWN; MAX_CPUS]
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Error {
+    /// Interrupts are still delivered by the 8259, there is no way to send IPIs.
+    NoApic,
+    NoMadt,
+    /// No free page below 1 MiB for the trampoline.
+    NoLowMemory,
+    /// The page tables cannot be loaded from real mode.
+    PageTablesTooHigh,
+    Memory(memory::Error),
+}
+
+impl From<memory::Error> for Error {
+    fn from(error: memory::Error) -> Self {
+        Error::Memory(error)
+    }
+}
+
+/// Number of CPUs running, the bootstrap processor included.
+pub fn online_cpus() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// Sends the wakeup IPI to CPU `cpu`, which leaves `hlt` if it is halted.
+pub fn wake(cpu: usize) {
+    if cpu < online_cpus() {
+        apic::send_ipi(
+            APIC_IDS[cpu].load(Ordering::Relaxed),
+            InterruptIndex::Wakeup.as_u8(),
+        );
+    }
+}
+
+fn init_cpu(cpu: usize, apic_id: u8) {
+    APIC_IDS[cpu].store(apic_id, Ordering::Relaxed);
+    percpu::init(cpu, apic_id);
+}
+
+/// Sets up the per-CPU data of the bootstrap processor and starts the other
+/// processors, returns how many CPUs are online.
+///
+/// Needs the heap, `acpi::init` and `apic::init`.
+pub fn init() -> Result<usize, Error> {
+    let bsp = apic::local_apic_id().ok_or(Error::NoApic)?;
+    if percpu::current().is_none() {
+        init_cpu(0, bsp);
+    }
+
+    let madt = Madt::get().ok_or(Error::NoMadt)?;
+    let trampoline = Trampoline::install()?;
+
+    for apic_id in madt.processors().filter(|&apic_id| apic_id != bsp as u32) {
+        // Higher IDs need x2APIC mode to be addressed.
+        if apic_id > u8::MAX as u32 {
+            println!("CPU with APIC ID {} skipped", apic_id);
+            continue;
+        }
+        if NEXT_CPU.load(Ordering::Acquire) == MAX_CPUS {
+            break;
+        }
+
+        if !start(&trampoline, apic_id as u8)? {
+            println!("CPU with APIC ID {} did not start", apic_id);
+        }
+    }
+
+    Ok(online_cpus())
+}
+
+/// Sends INIT and up to two startup IPIs, as the MP specification asks, then
+/// waits for the processor to check in.
+///
+/// A processor that does not check in in time is sent back to waiting for a
+/// startup IPI with another INIT, so its stack can be freed and the trampoline
+/// used for the next one.
+fn start(trampoline: &Trampoline, apic_id: u8) -> Result<bool, Error> {
+    let stack = FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_contiguous(AP_STACK_FRAMES, 1)
+        .ok_or(memory::Error::OutOfFrames)?;
+    let stack_top =
+        memory::phys_to_virt(stack.start.start_address()) + AP_STACK_FRAMES as u64 * FRAME_SIZE;
+
+    trampoline.prepare(ap_main, stack_top)?;
+    START_STATE.store(WAITING, Ordering::Release);
+
+    apic::send_init(apic_id);
+    time::udelay(INIT_DELAY_MICROS);
+    for _ in 0..2 {
+        apic::send_startup(apic_id, trampoline.page());
+        time::udelay(STARTUP_DELAY_MICROS);
+        if START_STATE.load(Ordering::Acquire) != WAITING {
+            break;
+        }
+    }
+
+    let deadline = time::nanos() + START_TIMEOUT_MICROS * 1000;
+    while START_STATE.load(Ordering::Acquire) == WAITING && time::nanos() < deadline {
+        core::hint::spin_loop();
+    }
+
+    // Either the processor claims its start or it is abandoned, never both.
+    let abandoned = START_STATE
+        .compare_exchange(WAITING, ABANDONED, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok();
+    if abandoned {
+        apic::send_init(apic_id);
+        time::udelay(INIT_DELAY_MICROS);
+        unsafe {
+            FRAME_ALLOCATOR
+                .get()
+                .unwrap()
+                .lock()
+                .deallocate_contiguous(stack)
+        };
+        return Ok(false);
+    }
+
+    while START_STATE.load(Ordering::Acquire) != STARTED {
+        core::hint::spin_loop();
+    }
+    Ok(true)
+}
+
+/// Called by the trampoline on the stack of the new processor.
+extern "C" fn ap_main() -> ! {
+    if START_STATE
+        .compare_exchange(WAITING, CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        // Too late, interrupts are still off and the coming INIT parks the processor.
+        loop {
+            x86_64::instructions::hlt();
+        }
+    }
+    let cpu = NEXT_CPU.fetch_add(1, Ordering::AcqRel);
+
+    gdt::init_ap();
+    interrupts::init_idt();
+    apic::init_ap();
+    init_cpu(cpu, apic::local_apic_id().unwrap_or(0));
+
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    START_STATE.store(STARTED, Ordering::Release);
+
+    x86_64::instructions::interrupts::enable();
+    task::executor::run_ap();
+}
@@ -0,0 +1,69 @@
+//! Data of the CPU running the code, found through the GS base.
+
+use alloc::boxed::Box;
+use core::{
+    arch::asm,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use x86_64::{registers::model_specific::GsBase, VirtAddr};
+
+/// Set once the bootstrap processor has its data, before that every caller
+/// runs on it as CPU 0.
+static READY: AtomicBool = AtomicBool::new(false);
+
+#[repr(C)]
+pub struct PerCpu {
+    /// Address of the structure itself, read with `gs:[0]`.
+    this: *const PerCpu,
+    id: usize,
+    apic_id: u8,
+}
+
+// Only ever reached from its own CPU, or read after it is set up.
+unsafe impl Sync for PerCpu {}
+
+impl PerCpu {
+    /// Index of the CPU, 0 for the bootstrap processor.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn apic_id(&self) -> u8 {
+        self.apic_id
+    }
+}
+
+/// Allocates the data of the calling CPU and points its GS base at it.
+pub(super) fn init(id: usize, apic_id: u8) {
+    let data = Box::leak(Box::new(PerCpu {
+        this: ptr::null(),
+        id,
+        apic_id,
+    }));
+    let this: *const PerCpu = data;
+    data.this = this;
+
+    GsBase::write(VirtAddr::from_ptr(this));
+    if id == 0 {
+        READY.store(true, Ordering::Release);
+    }
+}
+
+/// Data of the calling CPU, `None` before `smp::init`.
+pub fn current() -> Option<&'static PerCpu> {
+    if !READY.load(Ordering::Acquire) {
+        return None;
+    }
+
+    let this: *const PerCpu;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) this, options(nostack, readonly, preserves_flags));
+        Some(&*this)
+    }
+}
+
+/// Index of the calling CPU.
+pub fn cpu_id() -> usize {
+    current().map_or(0, PerCpu::id)
+}
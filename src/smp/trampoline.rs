@@ -0,0 +1,194 @@
+//! Real mode code an application processor starts in after the startup IPI.
+//!
+//! The code is copied to a page below 1 MiB and switches straight from real
+//! mode to long mode with the page tables of the bootstrap processor, which
+//! also map that page to itself. It then calls the entry point on the stack it
+//! was given. Only addresses relative to the start are used, the GDT pointer
+//! and the far jump target are filled in once the page is known.
+
+use core::{
+    arch::global_asm,
+    mem, ptr,
+    sync::atomic::{fence, Ordering},
+};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{PageTableFlags, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+use super::Error;
+use crate::memory::{self, FRAME_ALLOCATOR};
+
+/// Startup IPIs can only point at a page below 1 MiB.
+const REAL_MODE_LIMIT: u64 = 0x10_0000;
+
+global_asm!(
+    r#"
+.pushsection .text.ap_trampoline, "ax"
+.code16
+.global ap_trampoline_start
+ap_trampoline_start:
+    cli
+    cld
+    mov ax, cs
+    mov ds, ax
+
+    // PAE
+    mov eax, cr4
+    or eax, 1 << 5
+    mov cr4, eax
+
+    mov eax, dword ptr [ap_trampoline_cr3 - ap_trampoline_start]
+    mov cr3, eax
+
+    // Long mode and no-execute in EFER.
+    mov ecx, 0xc0000080
+    rdmsr
+    or eax, (1 << 8) | (1 << 11)
+    wrmsr
+
+    lgdt [ap_trampoline_gdtr - ap_trampoline_start]
+
+    // Protection, write protect and paging at once.
+    mov eax, cr0
+    or eax, 0x80010001
+    mov cr0, eax
+
+    // jmp far dword [ap_trampoline_target]
+    .byte 0x66, 0xff, 0x2e
+    .word ap_trampoline_target - ap_trampoline_start
+
+.code64
+.global ap_trampoline_long_mode
+ap_trampoline_long_mode:
+    xor eax, eax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov fs, ax
+    mov gs, ax
+
+    mov rsp, qword ptr [rip + ap_trampoline_stack]
+    call qword ptr [rip + ap_trampoline_entry]
+    ud2
+
+.align 8
+.global ap_trampoline_gdt
+ap_trampoline_gdt:
+    .quad 0
+    .quad 0x00209a0000000000
+    .quad 0x0000920000000000
+.global ap_trampoline_gdtr
+ap_trampoline_gdtr:
+    .word 23
+    .long 0
+.global ap_trampoline_target
+ap_trampoline_target:
+    .long 0
+    .word 0x08
+
+.align 8
+.global ap_trampoline_cr3
+ap_trampoline_cr3:
+    .quad 0
+.global ap_trampoline_stack
+ap_trampoline_stack:
+    .quad 0
+.global ap_trampoline_entry
+ap_trampoline_entry:
+    .quad 0
+.global ap_trampoline_end
+ap_trampoline_end:
+.popsection
+"#
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_long_mode: u8;
+    static ap_trampoline_gdt: u8;
+    static ap_trampoline_gdtr: u8;
+    static ap_trampoline_target: u8;
+    static ap_trampoline_cr3: u8;
+    static ap_trampoline_stack: u8;
+    static ap_trampoline_entry: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Offset of a symbol of the trampoline from its start.
+fn offset(symbol: &u8) -> usize {
+    symbol as *const u8 as usize - unsafe { &ap_trampoline_start as *const u8 as usize }
+}
+
+pub type ApEntry = extern "C" fn() -> !;
+
+/// Copy of the trampoline in low memory.
+pub struct Trampoline {
+    frame: PhysFrame,
+}
+
+impl Trampoline {
+    /// Copies the trampoline to a free page below 1 MiB and maps that page to
+    /// itself. The page stays in place for as long as the kernel runs.
+    pub fn install() -> Result<Self, Error> {
+        let frame = FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .allocate_frame_below(PhysAddr::new(REAL_MODE_LIMIT))
+            .ok_or(Error::NoLowMemory)?;
+        unsafe { memory::identity_map(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE)? };
+
+        let trampoline = Trampoline { frame };
+        let base = frame.start_address().as_u64() as u32;
+        unsafe {
+            let length = offset(&ap_trampoline_end);
+            ptr::copy_nonoverlapping(&ap_trampoline_start, trampoline.pointer(0), length);
+
+            trampoline.write(
+                offset(&ap_trampoline_gdtr) + mem::size_of::<u16>(),
+                base + offset(&ap_trampoline_gdt) as u32,
+            );
+            trampoline.write(
+                offset(&ap_trampoline_target),
+                base + offset(&ap_trampoline_long_mode) as u32,
+            );
+        }
+        fence(Ordering::SeqCst);
+
+        Ok(trampoline)
+    }
+
+    /// Page number to put in the startup IPI.
+    pub fn page(&self) -> u8 {
+        (self.frame.start_address().as_u64() >> 12) as u8
+    }
+
+    /// Sets up the next processor to call `entry` on the stack that ends at
+    /// `stack_top`. The page tables must be below 4 GiB, the trampoline
+    /// loads CR3 in real mode.
+    pub fn prepare(&self, entry: ApEntry, stack_top: VirtAddr) -> Result<(), Error> {
+        let cr3 = Cr3::read().0.start_address().as_u64();
+        if cr3 > u32::MAX as u64 {
+            return Err(Error::PageTablesTooHigh);
+        }
+
+        unsafe {
+            self.write(offset(&ap_trampoline_cr3), cr3);
+            self.write(offset(&ap_trampoline_stack), stack_top.as_u64());
+            self.write(offset(&ap_trampoline_entry), entry as usize as u64);
+        }
+        // Visible to the processor before the startup IPI goes out.
+        fence(Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn pointer(&self, offset: usize) -> *mut u8 {
+        (memory::phys_to_virt(self.frame.start_address()) + offset).as_mut_ptr()
+    }
+
+    unsafe fn write<T>(&self, offset: usize, value: T) {
+        ptr::write_unaligned(self.pointer(offset) as *mut T, value);
+    }
+}
@@ -0,0 +1,96 @@
+//! Cooperative scheduling.
+//!
+//! Each time the executor polls a task, the task gets a budget of
+//! [`POLL_BUDGET`] operations. Futures that can stay ready for long, like
+//! streams fed by interrupts, spend one unit per item through [`poll_proceed`]
+//! and return `Pending` once the budget is gone, so a busy task goes back to
+//! the run queue instead of keeping its CPU.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::smp::{self, MAX_CPUS};
+
+/// Operations a task may do in one poll.
+pub const POLL_BUDGET: u32 = 128;
+/// Budget outside of the executor, such as in `block_on` loops.
+const UNLIMITED: u32 = u32::MAX;
+
+/// Budget left to the task running on each CPU.
+static BUDGETS: [AtomicU32; MAX_CPUS] = {
+    const BUDGET: AtomicU32 = AtomicU32::new(UNLIMITED);
+    [BUDGET; MAX_CPUS]
+};
+
+/// Runs `poll` with a fresh budget on the calling CPU.
+pub(super) fn with_budget<R>(poll: impl FnOnce() -> R) -> R {
+    let budget = &BUDGETS[smp::cpu_id()];
+    budget.store(POLL_BUDGET, Ordering::Relaxed);
+    let result = poll();
+    budget.store(UNLIMITED, Ordering::Relaxed);
+    result
+}
+
+/// Spends one unit of the budget of the running task. Once it is used up the
+/// task is woken to be polled again later and `Pending` is returned.
+pub fn poll_proceed(cx: &mut Context<'_>) -> Poll<()> {
+    let budget = &BUDGETS[smp::cpu_id()];
+    match budget.load(Ordering::Relaxed) {
+        UNLIMITED => Poll::Ready(()),
+        0 => {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+        left => {
+            budget.store(left - 1, Ordering::Relaxed);
+            Poll::Ready(())
+        }
+    }
+}
+
+/// Future returned by [`consume_budget`].
+pub struct ConsumeBudget {
+    _private: (),
+}
+
+impl Future for ConsumeBudget {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        poll_proceed(cx)
+    }
+}
+
+/// Spends one unit of the budget, for loops that do not go through a future
+/// which does so already.
+pub fn consume_budget() -> ConsumeBudget {
+    ConsumeBudget { _private: () }
+}
+
+/// Future returned by [`yield_now`].
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Lets the other tasks run before the current one continues.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
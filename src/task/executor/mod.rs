@@ -0,0 +1,391 @@
+//! Work-stealing executor running on every online CPU.
+//!
+//! Each CPU has a run queue of its own, tasks woken on a CPU go to its queue.
+//! A CPU with nothing left takes tasks from the global injection queue, then
+//! steals half of the tasks of another CPU, and halts in [`sleep_if_idle`]
+//! until a wakeup IPI arrives when even that fails.
+//!
+//! Every [`Priority`] has queues of its own. Bottom halves always go first,
+//! background tasks only when nothing else is runnable or every
+//! [`BACKGROUND_INTERVAL`] polls.
+//!
+//! A woken task is queued once however often it is woken, a wakeup while it
+//! is being polled has the polling CPU queue it again afterwards. The
+//! injection queue has no bound, so neither spawning nor waking ever fails.
+//!
+//! [`sleep_if_idle`]: Executor::sleep_if_idle
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, task::Wake, vec::Vec};
+use conquer_once::spin::OnceCell;
+use core::{
+    future::Future,
+    pin::Pin,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, Ordering},
+    task::{Context, Waker},
+    time::Duration,
+};
+use crossbeam_queue::SegQueue;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use self::queue::RunQueues;
+use super::{budget, join, JoinHandle, Priority, Task, TaskId};
+use crate::{smp, time};
+
+mod queue;
+
+/// Polls after which background tasks go before normal ones once.
+pub const BACKGROUND_INTERVAL: usize = 16;
+
+static SCHEDULER: OnceCell<Scheduler> = OnceCell::uninit();
+/// CPUs halted waiting for work, one bit per CPU.
+static SLEEPING: AtomicU64 = AtomicU64::new(0);
+
+/// The task is in a run queue, or is to be queued again once polled.
+const SCHEDULED: u8 = 1 << 0;
+/// A CPU is polling the task.
+const RUNNING: u8 = 1 << 1;
+/// The task completed or was aborted, wakeups are ignored.
+const COMPLETE: u8 = 1 << 2;
+
+/// A spawned task as the scheduler sees it.
+pub(super) struct TaskCell {
+    id: TaskId,
+    name: Option<&'static str>,
+    priority: Priority,
+    /// `None` once the task completed or was aborted, locked by the CPU
+    /// polling it.
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    /// `SCHEDULED`, `RUNNING` and `COMPLETE` bits.
+    state: AtomicU8,
+    /// Next task in the injection queue holding this one.
+    next: AtomicPtr<TaskCell>,
+    aborted: AtomicBool,
+    polls: AtomicU64,
+    poll_nanos: AtomicU64,
+}
+
+impl TaskCell {
+    pub(super) fn new(
+        id: TaskId,
+        name: Option<&'static str>,
+        priority: Priority,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) -> Self {
+        TaskCell {
+            id,
+            name,
+            priority,
+            future: Mutex::new(Some(Box::pin(future))),
+            // Spawning queues it.
+            state: AtomicU8::new(SCHEDULED),
+            next: AtomicPtr::new(ptr::null_mut()),
+            aborted: AtomicBool::new(false),
+            polls: AtomicU64::new(0),
+            poll_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Has the executor drop the future instead of polling it.
+    pub(super) fn abort(self: &Arc<Self>) {
+        self.aborted.store(true, Ordering::Release);
+        self.wake_by_ref();
+    }
+
+    /// Marks the task as woken and returns whether it has to be queued, which
+    /// is not the case when it is queued already, being polled or complete.
+    fn schedule(&self) -> bool {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            if state & (SCHEDULED | COMPLETE) != 0 {
+                return false;
+            }
+            match self.state.compare_exchange_weak(
+                state,
+                state | SCHEDULED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return state & RUNNING == 0,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    fn info(&self) -> TaskInfo {
+        TaskInfo {
+            id: self.id,
+            name: self.name,
+            priority: self.priority,
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_time: Duration::from_nanos(self.poll_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Statistics of a live task, see [`tasks`].
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<&'static str>,
+    pub priority: Priority,
+    /// Times the task was polled.
+    pub polls: u64,
+    /// Time spent in those polls.
+    pub poll_time: Duration,
+}
+
+struct Scheduler {
+    queues: [RunQueues; Priority::COUNT],
+    /// Tasks spawned since a CPU last looked for work, registered and queued
+    /// by that CPU so that spawning takes no lock.
+    spawned: SegQueue<Arc<TaskCell>>,
+    /// Tasks that neither completed nor were aborted yet.
+    tasks: Mutex<BTreeMap<TaskId, Arc<TaskCell>>>,
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        Scheduler {
+            queues: [RunQueues::new(), RunQueues::new(), RunQueues::new()],
+            spawned: SegQueue::new(),
+            tasks: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Queues `task` on the calling CPU and wakes a halted CPU to take it.
+    ///
+    /// Called from wakers in interrupt context, so it must not allocate.
+    fn push(&self, task: Arc<TaskCell>) {
+        self.queues[task.priority as usize].push(smp::cpu_id(), task);
+        wake_one();
+    }
+
+    fn spawn(&self, task: Arc<TaskCell>) {
+        self.spawned.push(task);
+        wake_one();
+    }
+
+    /// Moves the spawned tasks into `tasks` and the injection queues.
+    fn register_spawned(&self) {
+        while let Ok(task) = self.spawned.pop() {
+            self.tasks.lock().insert(task.id, task.clone());
+            self.queues[task.priority as usize].inject(task);
+        }
+    }
+
+    fn next(&self, cpu: usize, polls: usize) -> Option<Arc<TaskCell>> {
+        self.register_spawned();
+
+        let order = if polls % BACKGROUND_INTERVAL == BACKGROUND_INTERVAL - 1 {
+            [Priority::BottomHalf, Priority::Background, Priority::Normal]
+        } else {
+            [Priority::BottomHalf, Priority::Normal, Priority::Background]
+        };
+
+        order
+            .iter()
+            .find_map(|&priority| self.queues[priority as usize].pop(cpu, polls))
+    }
+
+    fn has_work(&self) -> bool {
+        !self.spawned.is_empty() || self.queues.iter().any(|queues| !queues.is_empty())
+    }
+
+    fn poll(&self, task: Arc<TaskCell>) {
+        // Wakeups from here on queue the task again once it is polled.
+        task.state.store(RUNNING, Ordering::Release);
+        let mut future = task.future.lock();
+        if task.aborted.load(Ordering::Acquire) {
+            if future.take().is_some() {
+                self.tasks.lock().remove(&task.id);
+            }
+            task.state.store(COMPLETE, Ordering::Release);
+            return;
+        }
+
+        let waker = Waker::from(task.clone());
+        let mut context = Context::from_waker(&waker);
+        let start = time::nanos();
+        let ready = match future.as_mut() {
+            Some(future) => budget::with_budget(|| future.as_mut().poll(&mut context)).is_ready(),
+            None => true,
+        };
+        task.polls.fetch_add(1, Ordering::Relaxed);
+        task.poll_nanos
+            .fetch_add(time::nanos().saturating_sub(start), Ordering::Relaxed);
+
+        if ready {
+            *future = None;
+            task.state.store(COMPLETE, Ordering::Release);
+            drop(future);
+            self.tasks.lock().remove(&task.id);
+            return;
+        }
+        drop(future);
+        if task.state.fetch_and(!RUNNING, Ordering::AcqRel) & SCHEDULED != 0 {
+            self.push(task);
+        }
+    }
+}
+
+impl Wake for TaskCell {
+    fn wake(self: Arc<Self>) {
+        if self.schedule() {
+            if let Some(scheduler) = SCHEDULER.get() {
+                scheduler.push(self);
+            }
+        }
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.schedule() {
+            if let Some(scheduler) = SCHEDULER.get() {
+                scheduler.push(self.clone());
+            }
+        }
+    }
+}
+
+/// Handle to the executor shared by all CPUs.
+pub struct Executor {
+    scheduler: &'static Scheduler,
+}
+
+impl Executor {
+    /// Creates the executor on the first call, with a run queue for every CPU
+    /// online at that point, so `smp::init` must have run. Halted application
+    /// processors start running it.
+    pub fn new() -> Self {
+        if SCHEDULER.try_init_once(Scheduler::new).is_ok() {
+            wake_all();
+        }
+
+        Executor {
+            scheduler: SCHEDULER.get().unwrap(),
+        }
+    }
+
+    pub fn spawn<T: Send + 'static>(&self, task: Task<T>) -> JoinHandle<T> {
+        self.spawner().spawn(task)
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        Spawner {
+            scheduler: self.scheduler,
+        }
+    }
+
+    /// Runs tasks on the calling CPU forever.
+    pub fn run(&self) -> ! {
+        let cpu = smp::cpu_id();
+        let mut polls = 0;
+
+        loop {
+            while let Some(task) = self.scheduler.next(cpu, polls) {
+                self.scheduler.poll(task);
+                polls = polls.wrapping_add(1);
+            }
+            self.sleep_if_idle();
+        }
+    }
+
+    fn sleep_if_idle(&self) {
+        halt_unless(|| self.scheduler.has_work());
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns tasks onto the executor from any CPU, interrupt handlers excepted.
+#[derive(Clone)]
+pub struct Spawner {
+    scheduler: &'static Scheduler,
+}
+
+impl Spawner {
+    /// Hands `task` to the next CPU looking for work, without taking a lock.
+    pub fn spawn<T: Send + 'static>(&self, task: Task<T>) -> JoinHandle<T> {
+        let (cell, handle) = join::join(task);
+        self.scheduler.spawn(cell);
+        handle
+    }
+}
+
+/// Spawner of the executor, which must have been created.
+pub fn spawner() -> Spawner {
+    Spawner {
+        scheduler: SCHEDULER.get().expect("Executor not created"),
+    }
+}
+
+/// Live tasks of the executor, empty before it is created. Tasks spawned
+/// since a CPU last looked for work are not listed yet.
+pub fn tasks() -> Vec<TaskInfo> {
+    match SCHEDULER.get() {
+        Some(scheduler) => scheduler
+            .tasks
+            .lock()
+            .values()
+            .map(|task| task.info())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Idle loop of the application processors, they run the executor once the
+/// bootstrap processor created it.
+pub fn run_ap() -> ! {
+    loop {
+        if SCHEDULER.is_initialized() {
+            Executor::new().run();
+        }
+        halt_unless(|| SCHEDULER.is_initialized());
+    }
+}
+
+/// Halts the calling CPU until an interrupt arrives, unless `ready` holds once
+/// the CPU is marked as sleeping. A CPU queueing work after that point sees the
+/// mark and sends a wakeup IPI, which is held until `hlt`.
+fn halt_unless(ready: impl Fn() -> bool) {
+    let bit = 1 << smp::cpu_id();
+    interrupts::disable();
+    SLEEPING.fetch_or(bit, Ordering::SeqCst);
+
+    if ready() {
+        interrupts::enable();
+    } else {
+        interrupts::enable_and_hlt();
+    }
+    SLEEPING.fetch_and(!bit, Ordering::SeqCst);
+}
+
+/// Wakes one halted CPU other than the calling one.
+fn wake_one() {
+    let mut sleeping = SLEEPING.load(Ordering::SeqCst) & !(1 << smp::cpu_id());
+
+    while sleeping != 0 {
+        let cpu = sleeping.trailing_zeros() as usize;
+        let bit = 1 << cpu;
+        if SLEEPING.fetch_and(!bit, Ordering::SeqCst) & bit != 0 {
+            smp::wake(cpu);
+            return;
+        }
+        sleeping &= !bit;
+    }
+}
+
+fn wake_all() {
+    let sleeping = SLEEPING.swap(0, Ordering::SeqCst) & !(1 << smp::cpu_id());
+
+    for cpu in 0..smp::MAX_CPUS {
+        if sleeping & 1 << cpu != 0 {
+            smp::wake(cpu);
+        }
+    }
+}
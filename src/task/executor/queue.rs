@@ -0,0 +1,175 @@
+//! Run queues of the executor.
+//!
+//! A task sits in at most one queue at a time, which its scheduled bit
+//! guarantees. That lets the injection queue link tasks through themselves,
+//! so it is unbounded and queueing a task never allocates, not even from an
+//! interrupt handler.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crossbeam_queue::{ArrayQueue, PushError};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::TaskCell;
+use crate::smp;
+
+const LOCAL_QUEUE_SIZE: usize = 256;
+/// Polls after which a CPU looks at the injection queue even with local work,
+/// so that tasks spawned from outside are not starved.
+const INJECTION_INTERVAL: usize = 31;
+
+/// Unbounded FIFO of tasks linked through their `next` field.
+pub(super) struct TaskList {
+    ends: Mutex<Ends>,
+    len: AtomicUsize,
+}
+
+/// References taken out of `Arc`s with `Arc::into_raw`, owned by the list.
+struct Ends {
+    head: *const TaskCell,
+    tail: *const TaskCell,
+}
+
+// Only followed with the lock held.
+unsafe impl Send for Ends {}
+
+impl TaskList {
+    pub(super) const fn new() -> Self {
+        TaskList {
+            ends: Mutex::new(Ends {
+                head: ptr::null(),
+                tail: ptr::null(),
+            }),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// The lock is taken with interrupts off, wakers in interrupt handlers
+    /// push as well.
+    pub(super) fn push(&self, task: Arc<TaskCell>) {
+        let task = Arc::into_raw(task);
+        unsafe { (*task).next.store(ptr::null_mut(), Ordering::Relaxed) };
+
+        without_interrupts(|| {
+            let mut ends = self.ends.lock();
+            match unsafe { ends.tail.as_ref() } {
+                Some(tail) => tail.next.store(task as *mut TaskCell, Ordering::Relaxed),
+                None => ends.head = task,
+            }
+            ends.tail = task;
+            self.len.fetch_add(1, Ordering::Release);
+        });
+    }
+
+    pub(super) fn pop(&self) -> Option<Arc<TaskCell>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        without_interrupts(|| {
+            let mut ends = self.ends.lock();
+            let task = unsafe { ends.head.as_ref() }?;
+
+            ends.head = task.next.load(Ordering::Relaxed);
+            if ends.head.is_null() {
+                ends.tail = ptr::null();
+            }
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            Some(unsafe { Arc::from_raw(task) })
+        })
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Acquire) == 0
+    }
+}
+
+impl Drop for TaskList {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Queues of the tasks of one priority.
+pub(super) struct RunQueues {
+    /// Tasks spawned through a `Spawner` and the ones that did not fit into
+    /// a run queue.
+    injection_queue: TaskList,
+    /// Run queue of every CPU online when the executor was created.
+    local_queues: Vec<ArrayQueue<Arc<TaskCell>>>,
+}
+
+impl RunQueues {
+    pub(super) fn new() -> Self {
+        RunQueues {
+            injection_queue: TaskList::new(),
+            local_queues: (0..smp::online_cpus())
+                .map(|_| ArrayQueue::new(LOCAL_QUEUE_SIZE))
+                .collect(),
+        }
+    }
+
+    /// Queues `task` on `cpu`, or in the injection queue once that is full.
+    pub(super) fn push(&self, cpu: usize, task: Arc<TaskCell>) {
+        let task = match self.local_queues.get(cpu) {
+            Some(queue) => queue.push(task).err().map(|PushError(task)| task),
+            None => Some(task),
+        };
+        if let Some(task) = task {
+            self.inject(task);
+        }
+    }
+
+    pub(super) fn inject(&self, task: Arc<TaskCell>) {
+        self.injection_queue.push(task);
+    }
+
+    /// A CPU that came online after the executor was created has no run queue
+    /// and lives off the injection queue and stealing.
+    pub(super) fn pop(&self, cpu: usize, polls: usize) -> Option<Arc<TaskCell>> {
+        if polls % INJECTION_INTERVAL == 0 {
+            if let Some(task) = self.injection_queue.pop() {
+                return Some(task);
+            }
+        }
+        self.local_queues
+            .get(cpu)
+            .and_then(|local| local.pop().ok())
+            .or_else(|| self.injection_queue.pop())
+            .or_else(|| self.steal(cpu))
+    }
+
+    /// Takes a task from another CPU along with half of the ones it has left.
+    fn steal(&self, cpu: usize) -> Option<Arc<TaskCell>> {
+        let count = self.local_queues.len();
+
+        for offset in 1..=count {
+            let index = (cpu + offset) % count;
+            if index == cpu {
+                continue;
+            }
+            let victim = &self.local_queues[index];
+            let task = match victim.pop() {
+                Ok(task) => task,
+                Err(_) => continue,
+            };
+
+            for _ in 0..victim.len() / 2 {
+                match victim.pop() {
+                    Ok(stolen) => self.push(cpu, stolen),
+                    Err(_) => break,
+                }
+            }
+            return Some(task);
+        }
+        None
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.injection_queue.is_empty() && self.local_queues.iter().all(ArrayQueue::is_empty)
+    }
+}
@@ -0,0 +1,155 @@
+//! Handing the output of a spawned task to whoever waits for it.
+
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+use super::{executor::TaskCell, Task, TaskId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task was aborted before it completed.
+    Cancelled,
+}
+
+/// Output of a task, shared by the task and its [`JoinHandle`].
+struct JoinState<T> {
+    output: Mutex<Option<Result<T, JoinError>>>,
+    finished: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl<T> JoinState<T> {
+    fn finish(&self, output: Result<T, JoinError>) {
+        *self.output.lock() = Some(output);
+        self.finished.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+/// Future the executor runs in place of a task, it passes the output on to the
+/// handle. Dropped before that, it reports the task as cancelled.
+struct Completion<T> {
+    task: Task<T>,
+    state: Arc<JoinState<T>>,
+    done: bool,
+}
+
+impl<T> Future for Completion<T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let output = match this.task.poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        this.done = true;
+        this.state.finish(Ok(output));
+        Poll::Ready(())
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.state.finish(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+/// Waits for the output of a spawned task.
+///
+/// Dropping the handle aborts the task, [`detach`](Self::detach) lets it run
+/// to completion on its own.
+#[must_use = "dropping a JoinHandle aborts the task, call `detach` to let it run"]
+pub struct JoinHandle<T> {
+    id: TaskId,
+    state: Arc<JoinState<T>>,
+    /// `None` once detached.
+    task: Option<Arc<TaskCell>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Whether the task completed or was cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    /// Stops the task, its future is dropped the next time the executor gets
+    /// to it and the handle then resolves to [`JoinError::Cancelled`].
+    pub fn abort(&self) {
+        if let Some(task) = &self.task {
+            if !self.is_finished() {
+                task.abort();
+            }
+        }
+    }
+
+    /// Drops the handle without aborting the task.
+    pub fn detach(mut self) {
+        self.task = None;
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if !self.is_finished() {
+            self.state.waker.register(cx.waker());
+            if !self.is_finished() {
+                return Poll::Pending;
+            }
+        }
+
+        match self.state.output.lock().take() {
+            Some(output) => Poll::Ready(output),
+            None => panic!("JoinHandle polled after completion"),
+        }
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+/// Turns `task` into the form the executor queues and the handle to its output.
+pub(super) fn join<T: Send + 'static>(task: Task<T>) -> (Arc<TaskCell>, JoinHandle<T>) {
+    let (id, name, priority) = (task.id, task.name, task.priority);
+    let state = Arc::new(JoinState {
+        output: Mutex::new(None),
+        finished: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    });
+
+    let cell = Arc::new(TaskCell::new(
+        id,
+        name,
+        priority,
+        Completion {
+            task,
+            state: state.clone(),
+            done: false,
+        },
+    ));
+    let handle = JoinHandle {
+        id,
+        state,
+        task: Some(cell.clone()),
+    };
+    (cell, handle)
+}
@@ -1,5 +1,6 @@
 use core::task::Poll;
 
+use super::budget;
 use crate::{print, println};
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
@@ -30,6 +31,9 @@ impl Stream for ScancodeStream {
         cx: &mut core::task::Context<'_>,
     ) -> core::task::Poll<Option<Self::Item>> {
         let queue = SCANCODE_QUEUE.try_get().expect("not initialzed");
+        if budget::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
         if let Ok(scancode) = queue.pop() {
             return Poll::Ready(Some(scancode));
         }
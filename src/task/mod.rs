@@ -6,9 +6,17 @@ use core::{
     task::{Context, Poll},
 };
 
+pub mod budget;
 pub mod executor;
+pub mod join;
 pub mod keyboard;
 pub mod simple_executor;
+pub mod sync;
+pub mod timer;
+
+pub use budget::{consume_budget, yield_now};
+pub use executor::{spawner, tasks, Spawner, TaskInfo};
+pub use join::{JoinError, JoinHandle};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct TaskId(u64);
@@ -26,20 +34,56 @@ impl Default for TaskId {
     }
 }
 
-pub struct Task {
+/// Order in which runnable tasks are picked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Work deferred by interrupt handlers, run before anything else.
+    BottomHalf,
+    #[default]
+    Normal,
+    /// Runs when there is nothing else to do, and now and then regardless so
+    /// that it is not starved.
+    Background,
+}
+
+impl Priority {
+    pub const COUNT: usize = 3;
+}
+
+/// Future to be spawned, its output is handed to the [`JoinHandle`].
+pub struct Task<T = ()> {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    name: Option<&'static str>,
+    priority: Priority,
+    future: Pin<Box<dyn Future<Output = T> + Send>>,
 }
 
-impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+impl<T> Task<T> {
+    pub fn new(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
         Task {
             id: TaskId::new(),
+            name: None,
+            priority: Priority::Normal,
             future: Box::pin(future),
         }
     }
 
-    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+    /// Name shown in the list of [`tasks`].
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<T> {
         self.future.as_mut().poll(context)
     }
 }
@@ -0,0 +1,18 @@
+//! Synchronization between tasks.
+//!
+//! Tasks waiting on these primitives are not polled until they can make
+//! progress. The locks held inside are only taken for a few instructions and
+//! never by interrupt handlers, which must not use these types: they allocate
+//! when a task has to wait.
+
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+mod rwlock;
+mod semaphore;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, AcquireError, Semaphore, SemaphorePermit, TryAcquireError};
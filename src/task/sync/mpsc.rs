@@ -0,0 +1,267 @@
+//! Channels with many senders and one receiver.
+//!
+//! [`channel`] holds a bounded number of values and makes senders wait for
+//! room, [`unbounded_channel`] never does. Both are received from through a
+//! [`Receiver`], which yields `None` once every sender is gone and the values
+//! sent before are taken.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    fmt, future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::{task::AtomicWaker, Stream};
+use spin::Mutex;
+
+use super::{Semaphore, TryAcquireError};
+use crate::task::budget;
+
+/// The receiver is gone, the value is handed back.
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel holds as many values as it can.
+    Full(T),
+    /// The receiver is gone.
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// Nothing was sent, but senders are left.
+    Empty,
+    /// Nothing was sent and every sender is gone.
+    Disconnected,
+}
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+struct Chan<T> {
+    queue: Mutex<VecDeque<T>>,
+    /// Free slots of a bounded channel.
+    slots: Option<Semaphore>,
+    senders: AtomicUsize,
+    closed: AtomicBool,
+    rx_waker: AtomicWaker,
+}
+
+impl<T> Chan<T> {
+    fn new(slots: Option<Semaphore>) -> Arc<Self> {
+        Arc::new(Chan {
+            queue: Mutex::new(VecDeque::new()),
+            slots,
+            senders: AtomicUsize::new(1),
+            closed: AtomicBool::new(false),
+            rx_waker: AtomicWaker::new(),
+        })
+    }
+
+    /// Queues `value`, a bounded channel must have a slot taken for it.
+    fn push(&self, value: T) -> Result<(), SendError<T>> {
+        if self.is_closed() {
+            return Err(SendError(value));
+        }
+        self.queue.lock().push_back(value);
+        self.rx_waker.wake();
+        Ok(())
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.queue.lock().pop_front()?;
+        if let Some(slots) = &self.slots {
+            slots.add_permits(1);
+        }
+        Some(value)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn add_sender(self: &Arc<Self>) -> Arc<Self> {
+        self.senders.fetch_add(1, Ordering::Relaxed);
+        self.clone()
+    }
+
+    fn drop_sender(&self) {
+        if self.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.rx_waker.wake();
+        }
+    }
+}
+
+/// Creates a channel holding up to `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel needs room for a value");
+    let chan = Chan::new(Some(Semaphore::new(capacity)));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let chan = Chan::new(None);
+    (UnboundedSender { chan: chan.clone() }, Receiver { chan })
+}
+
+/// Sending half of a bounded [`channel`].
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T> {
+    fn slots(&self) -> &Semaphore {
+        self.chan.slots.as_ref().unwrap()
+    }
+
+    /// Waits for room in the channel and queues `value`.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match self.slots().acquire().await {
+            Ok(permit) => permit.forget(),
+            Err(_) => return Err(SendError(value)),
+        }
+        self.chan.push(value)
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        match self.slots().try_acquire() {
+            Ok(permit) => permit.forget(),
+            Err(TryAcquireError::NoPermits) => return Err(TrySendError::Full(value)),
+            Err(TryAcquireError::Closed) => return Err(TrySendError::Closed(value)),
+        }
+        self.chan
+            .push(value)
+            .map_err(|SendError(value)| TrySendError::Closed(value))
+    }
+
+    /// Whether the receiver is gone.
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// Sending half of an [`unbounded_channel`].
+pub struct UnboundedSender<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T> {
+    /// Queues `value` without waiting, which allocates, so this is no way to
+    /// pass data out of interrupt handlers.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.chan.push(value)
+    }
+
+    /// Whether the receiver is gone.
+    pub fn is_closed(&self) -> bool {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender {
+            chan: self.chan.add_sender(),
+        }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// Receiving half of a [`channel`] or [`unbounded_channel`].
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for the next value, `None` once every sender is gone and the
+    /// channel is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Spends a unit of the task's budget for every value received, so a
+    /// receiver that is never starved still yields its CPU now and then.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if budget::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        match self.try_recv() {
+            Ok(value) => return Poll::Ready(Some(value)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        self.chan.rx_waker.register(cx.waker());
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        // Read first, values sent by the last sender are queued by then.
+        let disconnected = self.chan.senders.load(Ordering::Acquire) == 0;
+        match self.chan.pop() {
+            Some(value) => Ok(value),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Makes further sends fail, values sent before can still be received.
+    pub fn close(&mut self) {
+        self.chan.closed.store(true, Ordering::Release);
+        if let Some(slots) = &self.chan.slots {
+            slots.close();
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
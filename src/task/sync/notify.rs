@@ -0,0 +1,144 @@
+//! Waking tasks without passing any data along.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+const WAITING: u8 = 0;
+/// Woken by `notify_one`, which is passed on if the future is dropped.
+const NOTIFIED_ONE: u8 = 1;
+const NOTIFIED_ALL: u8 = 2;
+
+/// A task waiting in [`Notified`].
+struct Waiter {
+    state: AtomicU8,
+    waker: AtomicWaker,
+}
+
+impl Waiter {
+    fn notify(&self, state: u8) {
+        self.state.store(state, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+struct State {
+    /// Set by `notify_one` when nobody waits, taken by the next waiter.
+    permit: bool,
+    waiters: VecDeque<Arc<Waiter>>,
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        match self.waiters.pop_front() {
+            Some(waiter) => waiter.notify(NOTIFIED_ONE),
+            None => self.permit = true,
+        }
+    }
+}
+
+/// Lets a task wait until another one tells it to go on.
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Notify {
+            state: Mutex::new(State {
+                permit: false,
+                waiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Wakes the task that waits longest. With nobody waiting, the next call
+    /// to [`notified`](Self::notified) completes right away.
+    pub fn notify_one(&self) {
+        self.state.lock().notify_one();
+    }
+
+    /// Wakes every task waiting at this point, nothing is kept for later ones.
+    pub fn notify_waiters(&self) {
+        for waiter in self.state.lock().waiters.drain(..) {
+            waiter.notify(NOTIFIED_ALL);
+        }
+    }
+
+    /// Waits for a notification. The future is queued when first polled.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            waiter: None,
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    /// Set once the future is queued.
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let waiter = match &this.waiter {
+            Some(waiter) => waiter,
+            None => {
+                let mut state = this.notify.state.lock();
+                if state.permit {
+                    state.permit = false;
+                    return Poll::Ready(());
+                }
+
+                let waiter = Arc::new(Waiter {
+                    state: AtomicU8::new(WAITING),
+                    waker: AtomicWaker::new(),
+                });
+                waiter.waker.register(cx.waker());
+                state.waiters.push_back(waiter.clone());
+                this.waiter = Some(waiter);
+                return Poll::Pending;
+            }
+        };
+
+        waiter.waker.register(cx.waker());
+        if waiter.state.load(Ordering::Acquire) == WAITING {
+            return Poll::Pending;
+        }
+        this.waiter = None;
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return,
+        };
+
+        let mut state = self.notify.state.lock();
+        match waiter.state.load(Ordering::Acquire) {
+            WAITING => state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter)),
+            NOTIFIED_ONE => state.notify_one(),
+            _ => {}
+        }
+    }
+}
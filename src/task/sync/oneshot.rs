@@ -0,0 +1,140 @@
+//! Channel for sending a single value between tasks.
+
+use alloc::sync::Arc;
+use core::{
+    future::{self, Future},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+/// The sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No value was sent yet.
+    Empty,
+    /// The sender was dropped without sending a value.
+    Closed,
+}
+
+struct Inner<T> {
+    value: Mutex<Option<T>>,
+    /// Set once the value is sent or the sender dropped.
+    complete: AtomicBool,
+    /// Set once the receiver is closed or dropped.
+    closed: AtomicBool,
+    rx_waker: AtomicWaker,
+    tx_waker: AtomicWaker,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        value: Mutex::new(None),
+        complete: AtomicBool::new(false),
+        closed: AtomicBool::new(false),
+        rx_waker: AtomicWaker::new(),
+        tx_waker: AtomicWaker::new(),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Hands `value` to the receiver, or back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.is_closed() {
+            return Err(value);
+        }
+        *self.inner.value.lock() = Some(value);
+        // Dropping `self` completes the channel.
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::Acquire)
+    }
+
+    /// Waits until the receiver is closed or dropped, for senders that can
+    /// stop working on a value nobody waits for any more.
+    pub async fn closed(&mut self) {
+        future::poll_fn(|cx| {
+            if self.is_closed() {
+                return Poll::Ready(());
+            }
+            self.inner.tx_waker.register(cx.waker());
+            if self.is_closed() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.complete.store(true, Ordering::Release);
+        self.inner.rx_waker.wake();
+    }
+}
+
+/// Future resolving to the value sent, or to [`RecvError`] if the sender was
+/// dropped instead.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Makes the sender fail, a value sent before stays available.
+    pub fn close(&mut self) {
+        self.inner.closed.store(true, Ordering::Release);
+        self.inner.tx_waker.wake();
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if !self.inner.complete.load(Ordering::Acquire) {
+            return Err(TryRecvError::Empty);
+        }
+        self.inner.value.lock().take().ok_or(TryRecvError::Closed)
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this.try_recv() {
+            Ok(value) => return Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => return Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        this.inner.rx_waker.register(cx.waker());
+        match this.try_recv() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,134 @@
+//! Reader-writer lock whose waiters sleep instead of spinning.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::{Semaphore, SemaphorePermit};
+
+/// Readers that may hold the lock at once. A writer takes all of them.
+const MAX_READERS: usize = 1 << 30;
+
+/// Many readers or one writer, in the order they asked for the lock. A waiting
+/// writer holds back the readers that come after it, so it is not starved.
+pub struct RwLock<T: ?Sized> {
+    semaphore: Semaphore,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        RwLock {
+            semaphore: Semaphore::new(MAX_READERS),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+        match self.semaphore.acquire().await {
+            Ok(permit) => RwLockReadGuard {
+                lock: self,
+                _permit: permit,
+            },
+            Err(_) => unreachable!("the semaphore of a RwLock is never closed"),
+        }
+    }
+
+    pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+        match self.semaphore.acquire_many(MAX_READERS).await {
+            Ok(permit) => RwLockWriteGuard {
+                lock: self,
+                _permit: permit,
+            },
+            Err(_) => unreachable!("the semaphore of a RwLock is never closed"),
+        }
+    }
+
+    /// Takes a read lock unless a writer holds or waits for the lock.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire().ok()?;
+        Some(RwLockReadGuard {
+            lock: self,
+            _permit: permit,
+        })
+    }
+
+    /// Takes the write lock if nobody holds or waits for the lock.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let permit = self.semaphore.try_acquire_many(MAX_READERS).ok()?;
+        Some(RwLockWriteGuard {
+            lock: self,
+            _permit: permit,
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("value", &&*guard).finish(),
+            None => f.write_str("RwLock { <locked> }"),
+        }
+    }
+}
+
+/// Shared access to the value of a [`RwLock`].
+#[must_use = "the lock is released right away if the guard is dropped"]
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _permit: SemaphorePermit<'a>,
+}
+
+unsafe impl<T: ?Sized + Sync> Send for RwLockReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for RwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+/// Exclusive access to the value of a [`RwLock`].
+#[must_use = "the lock is released right away if the guard is dropped"]
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _permit: SemaphorePermit<'a>,
+}
+
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
@@ -0,0 +1,244 @@
+//! Counting semaphore handing out permits in the order they were asked for.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
+
+const WAITING: u8 = 0;
+const GRANTED: u8 = 1;
+const CLOSED: u8 = 2;
+
+/// The semaphore was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcquireError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryAcquireError {
+    Closed,
+    /// Not enough permits are left, or other tasks wait for them already.
+    NoPermits,
+}
+
+/// A task waiting in [`Acquire`].
+struct Waiter {
+    permits: usize,
+    /// `WAITING` until the permits are handed over or the semaphore closes.
+    state: AtomicU8,
+    waker: AtomicWaker,
+}
+
+impl Waiter {
+    fn finish(&self, state: u8) {
+        self.state.store(state, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+struct State {
+    permits: usize,
+    waiters: VecDeque<Arc<Waiter>>,
+    closed: bool,
+}
+
+impl State {
+    /// Hands permits to the waiters at the front as long as there are enough.
+    fn grant(&mut self) {
+        while let Some(waiter) = self.waiters.front() {
+            if waiter.permits > self.permits {
+                break;
+            }
+            self.permits -= waiter.permits;
+            waiter.finish(GRANTED);
+            self.waiters.pop_front();
+        }
+    }
+}
+
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                waiters: VecDeque::new(),
+                closed: false,
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    pub fn add_permits(&self, permits: usize) {
+        let mut state = self.state.lock();
+        state.permits += permits;
+        state.grant();
+    }
+
+    /// Fails every waiting and later acquire, the permits handed out stay
+    /// valid.
+    pub fn close(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        for waiter in state.waiters.drain(..) {
+            waiter.finish(CLOSED);
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().closed
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Waits until `permits` are available at once. Tasks that asked earlier
+    /// are served first, so a large request is not starved by smaller ones.
+    pub fn acquire_many(&self, permits: usize) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            permits,
+            waiter: None,
+        }
+    }
+
+    pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        self.try_acquire_many(1)
+    }
+
+    pub fn try_acquire_many(&self, permits: usize) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        let mut state = self.state.lock();
+        if state.closed {
+            return Err(TryAcquireError::Closed);
+        }
+        if !state.waiters.is_empty() || state.permits < permits {
+            return Err(TryAcquireError::NoPermits);
+        }
+
+        state.permits -= permits;
+        Ok(SemaphorePermit {
+            semaphore: self,
+            permits,
+        })
+    }
+}
+
+/// Future returned by [`Semaphore::acquire`] and [`Semaphore::acquire_many`].
+///
+/// Dropped while waiting it leaves the queue, permits it was handed in the
+/// meantime go back to the semaphore.
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    /// Set once the future is queued.
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Result<SemaphorePermit<'a>, AcquireError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let state = match &this.waiter {
+            Some(waiter) => {
+                waiter.waker.register(cx.waker());
+                waiter.state.load(Ordering::Acquire)
+            }
+            None => this.enqueue(cx),
+        };
+
+        match state {
+            GRANTED => {
+                this.waiter = None;
+                Poll::Ready(Ok(SemaphorePermit {
+                    semaphore: this.semaphore,
+                    permits: this.permits,
+                }))
+            }
+            CLOSED => {
+                this.waiter = None;
+                Poll::Ready(Err(AcquireError))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl Acquire<'_> {
+    /// Takes the permits right away if nobody waits for them already, and
+    /// queues the future otherwise.
+    fn enqueue(&mut self, cx: &mut Context<'_>) -> u8 {
+        let mut state = self.semaphore.state.lock();
+        if state.closed {
+            return CLOSED;
+        }
+        if state.waiters.is_empty() && state.permits >= self.permits {
+            state.permits -= self.permits;
+            return GRANTED;
+        }
+
+        let waiter = Arc::new(Waiter {
+            permits: self.permits,
+            state: AtomicU8::new(WAITING),
+            waker: AtomicWaker::new(),
+        });
+        waiter.waker.register(cx.waker());
+        state.waiters.push_back(waiter.clone());
+        self.waiter = Some(waiter);
+        WAITING
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let waiter = match self.waiter.take() {
+            Some(waiter) => waiter,
+            None => return,
+        };
+
+        let mut state = self.semaphore.state.lock();
+        match waiter.state.load(Ordering::Acquire) {
+            GRANTED => state.permits += waiter.permits,
+            WAITING => state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter)),
+            _ => return,
+        }
+        // Whoever waited behind it may be served now.
+        state.grant();
+    }
+}
+
+/// Permits taken from a [`Semaphore`], given back when dropped.
+#[must_use = "dropping a permit gives it back right away"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_> {
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits out of the semaphore for good.
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
@@ -0,0 +1,236 @@
+//! Futures that complete after some time, woken from the timer interrupt.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use futures_util::{task::AtomicWaker, Stream};
+
+use super::budget;
+use crate::time::{self, TimerId};
+
+/// Futures that can wait for the timer at once, more of them poll themselves
+/// on every run of the executor until there is room again.
+const MAX_SLEEPERS: usize = 128;
+
+struct Sleeper {
+    in_use: AtomicBool,
+    waker: AtomicWaker,
+}
+
+const SLEEPER: Sleeper = Sleeper {
+    in_use: AtomicBool::new(false),
+    waker: AtomicWaker::new(),
+};
+
+static SLEEPERS: [Sleeper; MAX_SLEEPERS] = [SLEEPER; MAX_SLEEPERS];
+
+/// Slot in [`SLEEPERS`] and the timer that wakes it.
+struct Registration {
+    slot: usize,
+    timer: TimerId,
+}
+
+impl Registration {
+    fn new(delay: u64, waker: &Waker) -> Option<Self> {
+        let slot = SLEEPERS.iter().position(|sleeper| {
+            sleeper
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })?;
+        SLEEPERS[slot].waker.register(waker);
+
+        match time::schedule_ticks(delay, wake_sleeper, slot) {
+            Ok(timer) => Some(Registration { slot, timer }),
+            Err(_) => {
+                release(slot);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        time::cancel(self.timer);
+        release(self.slot);
+    }
+}
+
+fn release(slot: usize) {
+    SLEEPERS[slot].waker.take();
+    SLEEPERS[slot].in_use.store(false, Ordering::Release);
+}
+
+fn wake_sleeper(slot: usize) {
+    SLEEPERS[slot].waker.wake();
+}
+
+/// Future returned by [`sleep`].
+pub struct Sleep {
+    /// Tick at which the future completes.
+    deadline: u64,
+    registration: Option<Registration>,
+}
+
+impl Sleep {
+    fn until(deadline: u64) -> Self {
+        Sleep {
+            deadline,
+            registration: None,
+        }
+    }
+
+    fn is_elapsed(&self) -> bool {
+        time::ticks() >= self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.is_elapsed() {
+            this.registration = None;
+            return Poll::Ready(());
+        }
+
+        match &this.registration {
+            Some(registration) => SLEEPERS[registration.slot].waker.register(cx.waker()),
+            None => {
+                let delay = this.deadline - time::ticks();
+                this.registration = Registration::new(delay, cx.waker());
+                if this.registration.is_none() {
+                    cx.waker().wake_by_ref();
+                }
+            }
+        }
+
+        // The timer may have fired before the waker was registered.
+        if this.is_elapsed() {
+            this.registration = None;
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+/// Completes once `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep::until(time::ticks() + time::duration_to_ticks(duration))
+}
+
+/// Error of a [`timeout`] whose future did not complete in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+/// Future returned by [`timeout`].
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is pinned along with `self` and never moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut this.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
+
+/// Runs `future` for at most `duration`, it is dropped if it takes longer.
+pub fn timeout<F: Future>(future: F, duration: Duration) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+/// Stream returned by [`interval`].
+pub struct Interval {
+    period: u64,
+    sleep: Sleep,
+}
+
+impl Stream for Interval {
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        if budget::poll_proceed(cx).is_pending() {
+            return Poll::Pending;
+        }
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        // Periods missed while the task was busy are skipped, not delivered
+        // in a burst.
+        let next = (self.sleep.deadline + self.period).max(time::ticks() + 1);
+        self.sleep = Sleep::until(next);
+        Poll::Ready(Some(()))
+    }
+}
+
+/// Yields every `period`, starting one period from now.
+pub fn interval(period: Duration) -> Interval {
+    let period = time::duration_to_ticks(period).max(1);
+
+    Interval {
+        period,
+        sleep: Sleep::until(time::ticks() + period),
+    }
+}
+
+#[cfg(test)]
+fn block_on<F: Future>(future: F) -> F::Output {
+    use futures_util::{pin_mut, task::noop_waker_ref};
+
+    pin_mut!(future);
+    let mut context = Context::from_waker(noop_waker_ref());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn test_sleep() {
+    let start = time::uptime();
+    block_on(sleep(Duration::from_millis(5)));
+    assert!(time::uptime() - start >= Duration::from_millis(5));
+}
+
+#[test_case]
+fn test_timeout() {
+    let pending = timeout(core::future::pending::<()>(), Duration::from_millis(2));
+    assert_eq!(block_on(pending), Err(Elapsed));
+
+    let ready = timeout(core::future::ready(42), Duration::from_millis(2));
+    assert_eq!(block_on(ready), Ok(42));
+}
+
+#[test_case]
+fn test_interval() {
+    use futures_util::StreamExt;
+
+    let start = time::ticks();
+    let mut periods = interval(Duration::from_millis(2));
+    for _ in 0..3 {
+        block_on(periods.next());
+    }
+    assert!(time::ticks() - start >= 3 * periods.period);
+}
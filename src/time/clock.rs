@@ -0,0 +1,99 @@
+use core::{
+    hint,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{pit, ticks, DIVISOR, NANOS_PER_SECOND};
+
+/// Counter that can be read at any time with (close to) nanosecond resolution.
+pub trait ClockSource: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Nanoseconds since an arbitrary point in time, never going backwards.
+    fn nanos(&self) -> u64;
+
+    /// Busy-waits for at least `nanos` nanoseconds.
+    fn delay(&self, nanos: u64) {
+        let start = self.nanos();
+        while self.nanos() - start < nanos {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Timer ticks refined with the PIT counter, available from boot on.
+///
+/// Reads only advance while the timer interrupt is serviced, [`ClockSource::delay`]
+/// polls the counter instead so that it also works with interrupts disabled.
+pub struct PitClock {
+    /// Latest value handed out, reads that race with a tick must not go back.
+    last: AtomicU64,
+}
+
+impl PitClock {
+    pub const fn new() -> Self {
+        PitClock {
+            last: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Default for PitClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clocks_to_nanos(clocks: u64) -> u64 {
+    (clocks as u128 * NANOS_PER_SECOND / pit::PIT_FREQUENCY as u128) as u64
+}
+
+impl ClockSource for PitClock {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn nanos(&self) -> u64 {
+        let (ticks, count) = loop {
+            let before = ticks();
+            let count = pit::read_count();
+            if ticks() == before {
+                break (before, count);
+            }
+        };
+
+        let clocks = ticks * DIVISOR as u64 + DIVISOR.saturating_sub(count) as u64;
+        let nanos = clocks_to_nanos(clocks);
+        self.last.fetch_max(nanos, Ordering::Relaxed).max(nanos)
+    }
+
+    fn delay(&self, nanos: u64) {
+        let clocks = nanos as u128 * pit::PIT_FREQUENCY as u128;
+        let mut remaining = ((clocks + NANOS_PER_SECOND - 1) / NANOS_PER_SECOND) as u64;
+
+        let mut last = pit::read_count();
+        while remaining > 0 {
+            let count = pit::read_count();
+            // The counter runs down to 1 and reloads with the divisor.
+            let elapsed = if count <= last {
+                (last - count) as u64
+            } else {
+                last as u64 + DIVISOR as u64 - count as u64
+            };
+            remaining = remaining.saturating_sub(elapsed);
+            last = count;
+            hint::spin_loop();
+        }
+    }
+}
+
+#[test_case]
+fn test_pit_clock() {
+    let clock = PitClock::new();
+    let start_ticks = ticks();
+    let start = clock.nanos();
+
+    clock.delay(3_000_000);
+    assert!(clock.nanos() - start >= 2_000_000);
+    assert!(ticks() - start_ticks >= 2);
+}
@@ -0,0 +1,79 @@
+use x86_64::PhysAddr;
+
+use super::ClockSource;
+use crate::memory::{self, map_mmio, MmioRegion};
+
+const CAPABILITIES: usize = 0x00;
+const CONFIGURATION: usize = 0x10;
+const MAIN_COUNTER: usize = 0xf0;
+
+const COUNT_SIZE_64: u64 = 1 << 13;
+const ENABLE: u64 = 1 << 0;
+
+const FEMTOS_PER_NANO: u128 = 1_000_000;
+/// Longest counter period the specification allows, 100 ns.
+const MAX_PERIOD: u64 = 100_000_000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum HpetError {
+    /// The main counter is 32 bits wide, it wraps every few minutes, too often
+    /// for a clock.
+    Counter32Bit,
+    /// The counter period is zero or longer than the specification allows.
+    InvalidPeriod(u64),
+    Memory(memory::Error),
+}
+
+impl From<memory::Error> for HpetError {
+    fn from(error: memory::Error) -> Self {
+        HpetError::Memory(error)
+    }
+}
+
+/// High Precision Event Timer, only its main counter is used.
+pub struct Hpet {
+    registers: MmioRegion,
+    /// Femtoseconds per count of the main counter.
+    period: u64,
+}
+
+impl Hpet {
+    /// Maps the registers at `address` and starts the main counter, unless the
+    /// capabilities rule the HPET out as a clock.
+    pub fn new(address: PhysAddr) -> Result<Self, HpetError> {
+        let registers = map_mmio(address, 0x400)?;
+        let capabilities = registers.read64(CAPABILITIES);
+        let period = capabilities >> 32;
+
+        if capabilities & COUNT_SIZE_64 == 0 {
+            return Err(HpetError::Counter32Bit);
+        }
+        if period == 0 || period > MAX_PERIOD {
+            return Err(HpetError::InvalidPeriod(period));
+        }
+
+        let configuration = registers.read64(CONFIGURATION);
+        registers.write64(CONFIGURATION, configuration | ENABLE);
+
+        Ok(Hpet { registers, period })
+    }
+
+    /// Counts per second.
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.registers.read64(MAIN_COUNTER)
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn nanos(&self) -> u64 {
+        (self.counter() as u128 * self.period as u128 / FEMTOS_PER_NANO) as u64
+    }
+}
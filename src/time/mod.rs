@@ -0,0 +1,170 @@
+//! Monotonic kernel clock kept by the timer interrupt.
+
+mod clock;
+mod hpet;
+mod pit;
+pub mod rtc;
+pub mod tsc;
+pub mod wheel;
+
+pub use clock::{ClockSource, PitClock};
+pub use hpet::{Hpet, HpetError};
+pub use rtc::DateTime;
+pub use tsc::Tsc;
+pub use wheel::{
+    cancel, schedule, schedule_periodic, schedule_ticks, TimerCallback, TimerError, TimerId,
+};
+
+use conquer_once::spin::OnceCell;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    acpi::HpetTable,
+    interrupts::{irq::IRQ_TIMER, register_irq, IrqResult},
+};
+
+/// Rate the timer interrupt is programmed to.
+pub const TIMER_HZ: u64 = 1000;
+
+const DIVISOR: u16 = pit::divisor_for(TIMER_HZ);
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Timer interrupts since [`init`].
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+static PIT_CLOCK: PitClock = PitClock::new();
+static HPET: OnceCell<Hpet> = OnceCell::uninit();
+static TSC: OnceCell<Tsc> = OnceCell::uninit();
+/// Unix time at which the uptime was zero.
+static BOOT_TIME: OnceCell<Duration> = OnceCell::uninit();
+/// Source picked by [`init_clock_source`].
+static CLOCK: OnceCell<&'static dyn ClockSource> = OnceCell::uninit();
+
+/// Starts the PIT at [`TIMER_HZ`] and hooks up its interrupt, then reads the
+/// wall-clock time from the RTC.
+pub fn init() {
+    pit::set_divisor(DIVISOR);
+    register_irq(IRQ_TIMER, timer_handler).expect("Timer IRQ unavailable");
+
+    let timestamp = Duration::from_secs(rtc::read().to_unix_timestamp());
+    BOOT_TIME.init_once(|| timestamp.saturating_sub(uptime()));
+    rtc::init().expect("RTC IRQ unavailable");
+}
+
+/// Picks the best clock source: an invariant TSC, then the HPET, then the PIT.
+///
+/// Needs `acpi::init` to find the HPET, the TSC is calibrated against the HPET
+/// if there is one.
+pub fn init_clock_source() {
+    if let Some(address) = HpetTable::get().and_then(HpetTable::address) {
+        if let Ok(hpet) = Hpet::new(address) {
+            HPET.init_once(|| hpet);
+        }
+    }
+
+    let reference: &dyn ClockSource = match HPET.get() {
+        Some(hpet) => hpet,
+        None => &PIT_CLOCK,
+    };
+    if tsc::is_invariant() {
+        let tsc = Tsc::calibrate(reference);
+        TSC.init_once(|| tsc);
+    }
+
+    let best: &'static dyn ClockSource = match (TSC.get(), HPET.get()) {
+        (Some(tsc), _) => tsc,
+        (None, Some(hpet)) => hpet,
+        (None, None) => &PIT_CLOCK,
+    };
+    CLOCK.init_once(|| best);
+}
+
+/// Clock source in use, the PIT until [`init_clock_source`] ran.
+pub fn clock_source() -> &'static dyn ClockSource {
+    CLOCK.get().copied().unwrap_or(&PIT_CLOCK)
+}
+
+/// Nanoseconds read from the [`clock_source`].
+pub fn nanos() -> u64 {
+    clock_source().nanos()
+}
+
+/// Busy-waits for `nanos` nanoseconds.
+pub fn ndelay(nanos: u64) {
+    clock_source().delay(nanos);
+}
+
+/// Busy-waits for `micros` microseconds.
+pub fn udelay(micros: u64) {
+    ndelay(micros * 1000);
+}
+
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since [`init`], it never goes backwards.
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks())
+}
+
+/// Unix time the kernel booted at, to the second of the RTC.
+pub fn boot_time() -> Duration {
+    BOOT_TIME.get().copied().unwrap_or_default()
+}
+
+/// Wall-clock time since the Unix epoch, following the monotonic clock from
+/// the RTC reading at boot on.
+pub fn now() -> Duration {
+    boot_time() + uptime()
+}
+
+/// Length of `ticks` timer interrupts.
+///
+/// The PIT cannot hit [`TIMER_HZ`] exactly, so this uses the real period
+/// rather than drifting by the rounding error on every tick.
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let nanos = ticks as u128 * DIVISOR as u128 * NANOS_PER_SECOND / pit::PIT_FREQUENCY as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+/// Ticks needed for `duration` to have fully elapsed, rounded up.
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let clocks = duration.as_nanos() * pit::PIT_FREQUENCY as u128;
+    let period = DIVISOR as u128 * NANOS_PER_SECOND;
+    ((clocks + period - 1) / period) as u64
+}
+
+fn timer_handler(_line: u8) -> IrqResult {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    wheel::run_expired(now);
+    IrqResult::Handled
+}
+
+#[test_case]
+fn test_uptime_advances() {
+    let start = uptime();
+    let target = ticks() + 3;
+    while ticks() < target {
+        x86_64::instructions::hlt();
+    }
+    assert!(uptime() > start);
+}
+
+#[test_case]
+fn test_now_is_calendar_time() {
+    // 2020-01-01, the RTC of the test machine is not older than that.
+    assert!(now().as_secs() > 1_577_836_800);
+    assert!(now() >= boot_time());
+}
+
+#[test_case]
+fn test_tick_conversion() {
+    assert_eq!(duration_to_ticks(Duration::ZERO), 0);
+    assert_eq!(duration_to_ticks(ticks_to_duration(10)), 10);
+    let second = Duration::from_secs(1);
+    assert!(ticks_to_duration(duration_to_ticks(second)) >= second);
+}
@@ -0,0 +1,48 @@
+use x86_64::instructions::port::Port;
+
+/// Input clock of the programmable interval timer.
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+/// Channel 0, low then high byte, mode 2 (rate generator), binary.
+const RATE_GENERATOR: u8 = 0b0011_0100;
+const LATCH_CHANNEL_0: u8 = 0b0000_0000;
+
+/// Reload value giving the tick rate closest to `hz`.
+pub const fn divisor_for(hz: u64) -> u16 {
+    let divisor = (PIT_FREQUENCY + hz / 2) / hz;
+    if divisor > u16::MAX as u64 {
+        u16::MAX
+    } else if divisor == 0 {
+        1
+    } else {
+        divisor as u16
+    }
+}
+
+/// Makes channel 0 raise IRQ 0 every `divisor` input clocks.
+pub fn set_divisor(divisor: u16) {
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut channel: Port<u8> = Port::new(CHANNEL_0);
+
+    unsafe {
+        command.write(RATE_GENERATOR);
+        channel.write(divisor as u8);
+        channel.write((divisor >> 8) as u8);
+    }
+}
+
+/// Current value of the channel 0 counter, it counts down to 1 and reloads.
+pub fn read_count() -> u16 {
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut channel: Port<u8> = Port::new(CHANNEL_0);
+
+    // Latches the counter so that both bytes come from the same value.
+    unsafe {
+        command.write(LATCH_CHANNEL_0);
+        let low = channel.read() as u16;
+        let high = channel.read() as u16;
+        high << 8 | low
+    }
+}
@@ -0,0 +1,478 @@
+//! Battery backed real-time clock in the CMOS.
+
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use crate::interrupts::{irq::IRQ_RTC, register_irq, IrqError, IrqResult};
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+// Clock registers.
+const SECONDS: u8 = 0x00;
+const SECONDS_ALARM: u8 = 0x01;
+const MINUTES: u8 = 0x02;
+const MINUTES_ALARM: u8 = 0x03;
+const HOURS: u8 = 0x04;
+const HOURS_ALARM: u8 = 0x05;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+/// Not standard, but where every PC firmware keeps the century.
+const CENTURY: u8 = 0x32;
+const STATUS_A: u8 = 0x0a;
+const STATUS_B: u8 = 0x0b;
+const STATUS_C: u8 = 0x0c;
+
+const A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const A_RATE: u8 = 0x0f;
+const B_24_HOUR: u8 = 1 << 1;
+const B_BINARY: u8 = 1 << 2;
+const B_ALARM_INTERRUPT: u8 = 1 << 5;
+const B_PERIODIC_INTERRUPT: u8 = 1 << 6;
+const C_ALARM: u8 = 1 << 5;
+const C_PERIODIC: u8 = 1 << 6;
+const HOUR_PM: u8 = 1 << 7;
+/// Alarm field value that matches every second, minute or hour.
+const ALARM_ANY: u8 = 0xc0;
+
+/// Base of the periodic interrupt rate, which is this divided by a power of two.
+const PERIODIC_BASE_HZ: u32 = 32768;
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+/// Called in interrupt context, so it must not block or allocate.
+pub type RtcCallback = fn();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Periodic rates are powers of two from 2 to 8192 Hz.
+    InvalidRate,
+    /// Alarm field out of range.
+    InvalidTime,
+    Irq(IrqError),
+}
+
+impl From<IrqError> for Error {
+    fn from(value: IrqError) -> Self {
+        Error::Irq(value)
+    }
+}
+
+struct Cmos {
+    index: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Cmos {
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.index.write(register);
+            self.data.read()
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        unsafe {
+            self.index.write(register);
+            self.data.write(value);
+        }
+    }
+
+    /// Mode of the clock registers, set by the firmware.
+    fn mode(&mut self) -> Mode {
+        let status = self.read(STATUS_B);
+        Mode {
+            binary: status & B_BINARY != 0,
+            hours_24: status & B_24_HOUR != 0,
+        }
+    }
+
+    fn raw_time(&mut self) -> [u8; 7] {
+        while self.read(STATUS_A) & A_UPDATE_IN_PROGRESS != 0 {
+            core::hint::spin_loop();
+        }
+
+        [SECONDS, MINUTES, HOURS, DAY, MONTH, YEAR, CENTURY].map(|register| self.read(register))
+    }
+}
+
+/// Register and callback accesses are serialized with the interrupt handler.
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos {
+    index: Port::new(CMOS_INDEX),
+    data: Port::new(CMOS_DATA),
+});
+static PERIODIC: Mutex<Option<RtcCallback>> = Mutex::new(None);
+static ALARM: Mutex<Option<RtcCallback>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+struct Mode {
+    binary: bool,
+    hours_24: bool,
+}
+
+impl Mode {
+    fn decode(&self, value: u8) -> u8 {
+        if self.binary {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0x0f)
+        }
+    }
+
+    fn encode(&self, value: u8) -> u8 {
+        if self.binary {
+            value
+        } else {
+            (value / 10) << 4 | value % 10
+        }
+    }
+
+    fn decode_hour(&self, value: u8) -> u8 {
+        let hour = self.decode(value & !HOUR_PM);
+        if self.hours_24 {
+            hour
+        } else {
+            // 12 AM is midnight and 12 PM noon.
+            hour % 12 + if value & HOUR_PM != 0 { 12 } else { 0 }
+        }
+    }
+
+    fn encode_hour(&self, hour: u8) -> u8 {
+        if self.hours_24 {
+            return self.encode(hour);
+        }
+
+        let twelve_hour = match hour % 12 {
+            0 => 12,
+            hour => hour,
+        };
+        self.encode(twelve_hour) | if hour >= 12 { HOUR_PM } else { 0 }
+    }
+}
+
+/// Calendar date and time of day in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    pub fn to_unix_timestamp(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        days as u64 * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    pub fn from_unix_timestamp(timestamp: u64) -> Self {
+        let (year, month, day) = civil_from_days((timestamp / SECONDS_PER_DAY) as i64);
+        let seconds = timestamp % SECONDS_PER_DAY;
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Days between 1970-01-01 and the given date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years start in March so that the leap day is the last day of the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Reads the current date and time.
+///
+/// The registers are read until two passes agree, so that an update of the clock
+/// in the middle of a read cannot tear the result.
+pub fn read() -> DateTime {
+    let (raw, mode) = without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+        let mut raw = cmos.raw_time();
+        loop {
+            let again = cmos.raw_time();
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, cmos.mode())
+    });
+
+    let [second, minute, hour, day, month, year, century] = raw;
+    let century = match mode.decode(century) {
+        century @ 19..=21 => century as u16,
+        _ => 20,
+    };
+
+    DateTime {
+        year: century * 100 + mode.decode(year) as u16,
+        month: mode.decode(month),
+        day: mode.decode(day),
+        hour: mode.decode_hour(hour),
+        minute: mode.decode(minute),
+        second: mode.decode(second),
+    }
+}
+
+/// Hooks up IRQ 8, the interrupts themselves are enabled by [`enable_periodic`]
+/// and [`set_alarm`].
+pub(super) fn init() -> Result<(), Error> {
+    without_interrupts(|| {
+        let mut cmos = CMOS.lock();
+        let status = cmos.read(STATUS_B);
+        cmos.write(
+            STATUS_B,
+            status & !(B_PERIODIC_INTERRUPT | B_ALARM_INTERRUPT),
+        );
+        cmos.read(STATUS_C);
+    });
+
+    register_irq(IRQ_RTC, rtc_handler)?;
+    Ok(())
+}
+
+fn set_interrupt_enabled(interrupt: u8, enabled: bool) {
+    let mut cmos = CMOS.lock();
+    let status = cmos.read(STATUS_B);
+    cmos.write(
+        STATUS_B,
+        if enabled {
+            status | interrupt
+        } else {
+            status & !interrupt
+        },
+    );
+}
+
+/// Calls `callback` `hz` times per second, replacing the previous callback.
+pub fn enable_periodic(hz: u32, callback: RtcCallback) -> Result<(), Error> {
+    if !hz.is_power_of_two() || !(2..=8192).contains(&hz) {
+        return Err(Error::InvalidRate);
+    }
+    let rate = (PERIODIC_BASE_HZ / hz).trailing_zeros() as u8 + 1;
+
+    without_interrupts(|| {
+        *PERIODIC.lock() = Some(callback);
+
+        let mut cmos = CMOS.lock();
+        let status = cmos.read(STATUS_A);
+        cmos.write(STATUS_A, (status & !A_RATE) | rate);
+        drop(cmos);
+
+        set_interrupt_enabled(B_PERIODIC_INTERRUPT, true);
+    });
+    Ok(())
+}
+
+pub fn disable_periodic() {
+    without_interrupts(|| {
+        set_interrupt_enabled(B_PERIODIC_INTERRUPT, false);
+        *PERIODIC.lock() = None;
+    });
+}
+
+/// Calls `callback` whenever the time of day matches, `None` fields match any
+/// value. Replaces the previous alarm.
+pub fn set_alarm(
+    hour: Option<u8>,
+    minute: Option<u8>,
+    second: Option<u8>,
+    callback: RtcCallback,
+) -> Result<(), Error> {
+    if hour.map_or(false, |hour| hour > 23)
+        || minute.map_or(false, |minute| minute > 59)
+        || second.map_or(false, |second| second > 59)
+    {
+        return Err(Error::InvalidTime);
+    }
+
+    without_interrupts(|| {
+        *ALARM.lock() = Some(callback);
+
+        let mut cmos = CMOS.lock();
+        let mode = cmos.mode();
+        cmos.write(
+            HOURS_ALARM,
+            hour.map_or(ALARM_ANY, |hour| mode.encode_hour(hour)),
+        );
+        cmos.write(
+            MINUTES_ALARM,
+            minute.map_or(ALARM_ANY, |minute| mode.encode(minute)),
+        );
+        cmos.write(
+            SECONDS_ALARM,
+            second.map_or(ALARM_ANY, |second| mode.encode(second)),
+        );
+        drop(cmos);
+
+        set_interrupt_enabled(B_ALARM_INTERRUPT, true);
+    });
+    Ok(())
+}
+
+pub fn clear_alarm() {
+    without_interrupts(|| {
+        set_interrupt_enabled(B_ALARM_INTERRUPT, false);
+        *ALARM.lock() = None;
+    });
+}
+
+fn rtc_handler(_line: u8) -> IrqResult {
+    // Reading register C acknowledges the interrupt, the RTC raises no other
+    // until it is read.
+    let flags = CMOS.lock().read(STATUS_C);
+
+    // Copied out so that callbacks can replace or disable themselves.
+    let periodic = *PERIODIC.lock();
+    let alarm = *ALARM.lock();
+
+    if let Some(callback) = periodic.filter(|_| flags & C_PERIODIC != 0) {
+        callback();
+    }
+    if let Some(callback) = alarm.filter(|_| flags & C_ALARM != 0) {
+        callback();
+    }
+
+    if flags & (C_PERIODIC | C_ALARM) != 0 {
+        IrqResult::Handled
+    } else {
+        IrqResult::NotMine
+    }
+}
+
+#[test_case]
+fn test_unix_timestamp() {
+    let dates = [
+        (
+            DateTime {
+                year: 1970,
+                month: 1,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            },
+            0,
+        ),
+        (
+            DateTime {
+                year: 2000,
+                month: 3,
+                day: 1,
+                hour: 0,
+                minute: 0,
+                second: 0,
+            },
+            951_868_800,
+        ),
+        (
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 12,
+                minute: 34,
+                second: 56,
+            },
+            1_709_210_096,
+        ),
+        (
+            DateTime {
+                year: 2099,
+                month: 12,
+                day: 31,
+                hour: 23,
+                minute: 59,
+                second: 59,
+            },
+            4_102_444_799,
+        ),
+    ];
+
+    for (date, timestamp) in dates {
+        assert_eq!(date.to_unix_timestamp(), timestamp);
+        assert_eq!(DateTime::from_unix_timestamp(timestamp), date);
+    }
+}
+
+#[test_case]
+fn test_hour_encoding() {
+    let mode = Mode {
+        binary: false,
+        hours_24: false,
+    };
+    assert_eq!(mode.encode_hour(0), 0x12);
+    assert_eq!(mode.encode_hour(13), 0x01 | HOUR_PM);
+    for hour in 0..24 {
+        assert_eq!(mode.decode_hour(mode.encode_hour(hour)), hour);
+    }
+}
+
+#[test_case]
+fn test_periodic_interrupt() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    fn count() {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+
+    enable_periodic(1024, count).unwrap();
+    let target = super::ticks() + 10;
+    while super::ticks() < target {
+        x86_64::instructions::hlt();
+    }
+    disable_periodic();
+
+    assert!(COUNT.load(Ordering::Relaxed) > 0);
+    assert_eq!(enable_periodic(1000, count), Err(Error::InvalidRate));
+}
@@ -0,0 +1,53 @@
+use core::arch::x86_64::{__cpuid, _rdtsc};
+
+use super::{ClockSource, NANOS_PER_SECOND};
+
+/// Time the TSC is measured against the reference clock for.
+const CALIBRATION_NANOS: u64 = 10_000_000;
+
+/// Whether the TSC ticks at a constant rate in every power state, according to
+/// CPUID. Without that it is unusable as a clock.
+pub fn is_invariant() -> bool {
+    let max_extended = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_extended >= 0x8000_0007 && unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0
+}
+
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Time stamp counter with a calibrated frequency.
+pub struct Tsc {
+    frequency: u64,
+}
+
+impl Tsc {
+    /// Measures the frequency of the TSC against `reference`.
+    pub fn calibrate(reference: &dyn ClockSource) -> Self {
+        let start_nanos = reference.nanos();
+        let start = read();
+        reference.delay(CALIBRATION_NANOS);
+        let end_nanos = reference.nanos();
+        let end = read();
+
+        let elapsed = (end_nanos - start_nanos).max(1) as u128;
+        Tsc {
+            frequency: ((end - start) as u128 * NANOS_PER_SECOND / elapsed) as u64,
+        }
+    }
+
+    /// Counts per second.
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+}
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn nanos(&self) -> u64 {
+        (read() as u128 * NANOS_PER_SECOND / self.frequency as u128) as u64
+    }
+}
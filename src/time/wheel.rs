@@ -0,0 +1,305 @@
+//! Hashed timer wheel advanced by the timer interrupt.
+//!
+//! Timers live in a fixed table so that firing them never allocates, a timer
+//! due in more than [`SLOTS`] ticks stays in its slot for several rounds.
+
+use core::time::Duration;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::{duration_to_ticks, ticks};
+
+const SLOTS: usize = 256;
+/// Timers that can be pending at once.
+pub const MAX_TIMERS: usize = 256;
+/// Callbacks taken out of the wheel before its lock is dropped to run them.
+const BATCH: usize = 16;
+
+/// Called in interrupt context with the data it was scheduled with, so it must
+/// not block or allocate.
+pub type TimerCallback = fn(data: usize);
+
+/// Handle of a scheduled timer, see [`cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId {
+    index: u16,
+    /// Tells apart the timers that used the same entry.
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// [`MAX_TIMERS`] timers are already pending.
+    Full,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    /// `None` while the entry is free.
+    callback: Option<TimerCallback>,
+    data: usize,
+    deadline: u64,
+    /// Ticks between two runs, 0 for a one-shot timer.
+    period: u64,
+    generation: u32,
+    /// Next entry in the same slot.
+    next: Option<u16>,
+}
+
+const FREE: Entry = Entry {
+    callback: None,
+    data: 0,
+    deadline: 0,
+    period: 0,
+    generation: 0,
+    next: None,
+};
+
+struct Wheel {
+    entries: [Entry; MAX_TIMERS],
+    slots: [Option<u16>; SLOTS],
+    /// Last tick whose slot was run.
+    current: u64,
+}
+
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel {
+    entries: [FREE; MAX_TIMERS],
+    slots: [None; SLOTS],
+    current: 0,
+});
+
+type Expired = [Option<(TimerCallback, usize)>; BATCH];
+
+impl Wheel {
+    fn slot(deadline: u64) -> usize {
+        deadline as usize % SLOTS
+    }
+
+    fn link(&mut self, index: u16) {
+        let slot = Self::slot(self.entries[index as usize].deadline);
+        self.entries[index as usize].next = self.slots[slot];
+        self.slots[slot] = Some(index);
+    }
+
+    fn unlink(&mut self, index: u16) {
+        let next = self.entries[index as usize].next;
+        let slot = Self::slot(self.entries[index as usize].deadline);
+
+        let mut cursor = self.slots[slot];
+        let mut previous: Option<u16> = None;
+        while let Some(current) = cursor {
+            if current == index {
+                match previous {
+                    Some(previous) => self.entries[previous as usize].next = next,
+                    None => self.slots[slot] = next,
+                }
+                return;
+            }
+            previous = cursor;
+            cursor = self.entries[current as usize].next;
+        }
+    }
+
+    fn release(&mut self, index: u16) {
+        let entry = &mut self.entries[index as usize];
+        entry.callback = None;
+        entry.generation = entry.generation.wrapping_add(1);
+    }
+
+    fn add(
+        &mut self,
+        delay: u64,
+        period: u64,
+        callback: TimerCallback,
+        data: usize,
+    ) -> Result<TimerId, TimerError> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.callback.is_none())
+            .ok_or(TimerError::Full)? as u16;
+
+        // Slots up to `current` were already passed over for this round.
+        let deadline = self.current.max(ticks()) + delay.max(1);
+        let entry = &mut self.entries[index as usize];
+        entry.callback = Some(callback);
+        entry.data = data;
+        entry.deadline = deadline;
+        entry.period = period;
+        let generation = entry.generation;
+
+        self.link(index);
+        Ok(TimerId { index, generation })
+    }
+
+    fn is_pending(&self, id: TimerId) -> bool {
+        self.entries.get(id.index as usize).map_or(false, |entry| {
+            entry.callback.is_some() && entry.generation == id.generation
+        })
+    }
+
+    /// Takes the timers of the slot of `tick` that are due, returns whether the
+    /// whole slot was handled.
+    fn expire(&mut self, tick: u64, expired: &mut Expired) -> bool {
+        let slot = Self::slot(tick);
+        let mut taken = [0u16; BATCH];
+        let mut count = 0;
+
+        let mut cursor = self.slots[slot];
+        let mut previous: Option<u16> = None;
+        let mut done = true;
+        while let Some(index) = cursor {
+            let entry = self.entries[index as usize];
+            cursor = entry.next;
+
+            if entry.deadline > tick {
+                previous = Some(index);
+                continue;
+            }
+            if count == BATCH {
+                done = false;
+                break;
+            }
+
+            match previous {
+                Some(previous) => self.entries[previous as usize].next = entry.next,
+                None => self.slots[slot] = entry.next,
+            }
+            expired[count] = entry.callback.map(|callback| (callback, entry.data));
+            taken[count] = index;
+            count += 1;
+        }
+
+        // Rearmed after the walk, a period that is a multiple of the wheel
+        // size puts the timer back into the slot being walked.
+        for &index in &taken[..count] {
+            let period = self.entries[index as usize].period;
+            if period == 0 {
+                self.release(index);
+            } else {
+                self.entries[index as usize].deadline = tick + period;
+                self.link(index);
+            }
+        }
+
+        done
+    }
+}
+
+/// Calls `callback` with `data` once `delay` has elapsed.
+pub fn schedule(
+    delay: Duration,
+    callback: TimerCallback,
+    data: usize,
+) -> Result<TimerId, TimerError> {
+    schedule_ticks(duration_to_ticks(delay), callback, data)
+}
+
+/// Calls `callback` with `data` after `delay` timer interrupts.
+pub fn schedule_ticks(
+    delay: u64,
+    callback: TimerCallback,
+    data: usize,
+) -> Result<TimerId, TimerError> {
+    without_interrupts(|| WHEEL.lock().add(delay, 0, callback, data))
+}
+
+/// Calls `callback` with `data` every `period` until the timer is cancelled.
+pub fn schedule_periodic(
+    period: Duration,
+    callback: TimerCallback,
+    data: usize,
+) -> Result<TimerId, TimerError> {
+    let period = duration_to_ticks(period).max(1);
+    without_interrupts(|| WHEEL.lock().add(period, period, callback, data))
+}
+
+/// Stops a timer, returns false if it already fired or was cancelled.
+pub fn cancel(id: TimerId) -> bool {
+    without_interrupts(|| {
+        let mut wheel = WHEEL.lock();
+        if !wheel.is_pending(id) {
+            return false;
+        }
+
+        wheel.unlink(id.index);
+        wheel.release(id.index);
+        true
+    })
+}
+
+/// Runs the timers due by tick `now`, called from the timer interrupt.
+///
+/// Callbacks run without the wheel locked so that they can schedule and
+/// cancel timers themselves.
+pub(super) fn run_expired(now: u64) {
+    loop {
+        let mut expired: Expired = [None; BATCH];
+        {
+            let mut wheel = WHEEL.lock();
+            while wheel.current < now {
+                let tick = wheel.current + 1;
+                if !wheel.expire(tick, &mut expired) {
+                    break;
+                }
+                wheel.current = tick;
+                if expired.iter().any(Option::is_some) {
+                    break;
+                }
+            }
+        }
+
+        if expired[0].is_none() {
+            return;
+        }
+        for (callback, data) in expired.iter().flatten() {
+            callback(*data);
+        }
+    }
+}
+
+#[cfg(test)]
+fn wait(duration: Duration) {
+    let target = ticks() + duration_to_ticks(duration);
+    while ticks() <= target {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn test_one_shot_and_cancel() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static FIRED: AtomicUsize = AtomicUsize::new(0);
+    fn fire(data: usize) {
+        FIRED.fetch_add(data, Ordering::Relaxed);
+    }
+
+    let cancelled = schedule(Duration::from_millis(2), fire, 100).unwrap();
+    let timer = schedule(Duration::from_millis(2), fire, 1).unwrap();
+    assert!(cancel(cancelled));
+    assert!(!cancel(cancelled));
+
+    wait(Duration::from_millis(5));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 1);
+    assert!(!cancel(timer));
+}
+
+#[test_case]
+fn test_periodic() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    fn run(_data: usize) {
+        RUNS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let timer = schedule_periodic(Duration::from_millis(1), run, 0).unwrap();
+    wait(Duration::from_millis(10));
+    assert!(cancel(timer));
+
+    let runs = RUNS.load(Ordering::Relaxed);
+    assert!(runs >= 5, "periodic timer ran {} times", runs);
+    wait(Duration::from_millis(3));
+    assert_eq!(RUNS.load(Ordering::Relaxed), runs);
+}
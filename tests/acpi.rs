@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(titan_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use titan_os::{
+    acpi::{self, Fadt, HpetTable, Madt, MadtEntry},
+    memory,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    acpi::init().expect("No ACPI tables");
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn tables_are_found() {
+    assert!(acpi::tables().count() >= 3);
+    assert!(acpi::find_table(b"NONE").is_none());
+    acpi::dump();
+}
+
+#[test_case]
+fn madt_lists_controllers() {
+    let madt = Madt::get().unwrap();
+    assert!(madt.processors().count() >= 1);
+    assert!(madt
+        .entries()
+        .any(|entry| matches!(entry, MadtEntry::IoApic { .. })));
+}
+
+#[test_case]
+fn fadt_has_power_management() {
+    let fadt = Fadt::get().unwrap();
+    assert!(fadt.pm1a_control_port().is_some());
+    assert!(!fadt.dsdt_address().is_null());
+}
+
+#[test_case]
+fn soft_off_sleep_type_is_found() {
+    assert!(acpi::dsdt().is_some());
+    assert!(acpi::sleep_type(b"_S5_").is_some());
+    assert!(acpi::sleep_type(b"_XX_").is_none());
+}
+
+#[test_case]
+fn hpet_is_memory_mapped() {
+    assert!(HpetTable::get().unwrap().address().is_some());
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    titan_os::test_panic_handler(info)
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(titan_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use titan_os::{
+    acpi, allocator, memory,
+    time::{self, PitClock},
+};
+use x86_64::instructions::interrupts::without_interrupts;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Initialization failed");
+    acpi::init().expect("No ACPI tables");
+    time::init_clock_source();
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn better_source_than_pit() {
+    // Machines without an HPET, like `-machine hpet=off`, may be left with the PIT.
+    if acpi::HpetTable::get().is_some() {
+        assert_ne!(time::clock_source().name(), "pit");
+    }
+}
+
+#[test_case]
+fn udelay_waits() {
+    let start = time::nanos();
+    time::udelay(500);
+    assert!(time::nanos() - start >= 500_000);
+}
+
+#[test_case]
+fn clock_agrees_with_pit() {
+    let clock = time::clock_source();
+    // Nothing was calibrated.
+    if clock.name() == "pit" {
+        return;
+    }
+
+    // The PIT counter is polled with interrupts off, so no tick can be missed
+    // and the timer interrupt does not stretch the measurement.
+    let elapsed = without_interrupts(|| {
+        let start = clock.nanos();
+        PitClock::new().delay(20_000_000);
+        clock.nanos() - start
+    });
+    assert!(
+        (15_000_000..=25_000_000).contains(&elapsed),
+        "20 ms on the PIT took {} ns on the {}",
+        elapsed,
+        clock.name()
+    );
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    titan_os::test_panic_handler(info)
+}
@@ -0,0 +1,273 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::{
+    future::Future,
+    panic::PanicInfo,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use titan_os::{
+    acpi, allocator, exit_qemu,
+    interrupts::apic,
+    memory, serial_print, serial_println, smp,
+    task::{
+        self, budget::POLL_BUDGET, executor::Executor, sync::Notify, JoinError, Priority, Task,
+    },
+    time, QemuExitStatus,
+};
+
+const TASKS: usize = 64;
+const POLLS: usize = 4;
+/// Far more tasks than fit into the run queues.
+const MANY_TASKS: usize = 5000;
+
+static DONE: AtomicUsize = AtomicUsize::new(0);
+/// CPUs that polled a task, one bit per CPU.
+static CPUS: AtomicU64 = AtomicU64::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Heap initialization failed");
+    acpi::init().expect("No ACPI tables");
+    apic::init().expect("APIC initialization failed");
+    time::init_clock_source();
+    smp::init().expect("SMP initialization failed");
+
+    let executor = Executor::new();
+    for _ in 0..TASKS {
+        executor.spawn(Task::new(busy_task())).detach();
+    }
+    executor.spawn(Task::new(run_tests())).detach();
+    executor.run();
+}
+
+/// Keeps its CPU busy for a while on every poll, so that the other CPUs have
+/// to steal tasks.
+async fn busy_task() {
+    for _ in 0..POLLS {
+        CPUS.fetch_or(1 << smp::cpu_id(), Ordering::Relaxed);
+        time::udelay(100);
+        task::yield_now().await;
+    }
+    DONE.fetch_add(1, Ordering::Relaxed);
+}
+
+async fn run_tests() {
+    serial_print!("executor::tasks_run_on_every_cpu...\t");
+    tasks_run_on_every_cpu().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::join_handle_returns_output...\t");
+    join_handle_returns_output().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::abort_cancels_task...\t");
+    abort_cancels_task().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::dropped_handle_aborts_task...\t");
+    dropped_handle_aborts_task().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::busy_task_yields_after_budget...\t");
+    busy_task_yields_after_budget().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::wakeups_are_deduplicated...\t");
+    wakeups_are_deduplicated().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::thousands_of_tasks_complete...\t");
+    thousands_of_tasks_complete().await;
+    serial_println!("[OK]");
+
+    serial_print!("executor::live_tasks_are_listed...\t");
+    live_tasks_are_listed().await;
+    serial_println!("[OK]");
+
+    exit_qemu(QemuExitStatus::Success);
+}
+
+async fn tasks_run_on_every_cpu() {
+    while DONE.load(Ordering::Relaxed) < TASKS {
+        task::yield_now().await;
+    }
+
+    let cpus = CPUS.load(Ordering::Relaxed).count_ones() as usize;
+    assert_eq!(cpus, smp::online_cpus());
+}
+
+async fn join_handle_returns_output() {
+    let child = task::spawner().spawn(Task::new(async { 6 * 7 }));
+    assert_eq!(child.await, Ok(42));
+}
+
+async fn abort_cancels_task() {
+    let child = task::spawner().spawn(Task::new(core::future::pending::<()>()));
+    child.abort();
+    assert_eq!(child.await, Err(JoinError::Cancelled));
+}
+
+async fn dropped_handle_aborts_task() {
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DROPPED.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let guard = Guard;
+    drop(task::spawner().spawn(Task::new(async move {
+        let _guard = guard;
+        core::future::pending::<()>().await;
+    })));
+    while !DROPPED.load(Ordering::Relaxed) {
+        task::yield_now().await;
+    }
+}
+
+async fn busy_task_yields_after_budget() {
+    /// Counts how often the future it wraps is polled.
+    struct CountPolls<F>(F, usize);
+
+    impl<F: Future + Unpin> Future for CountPolls<F> {
+        type Output = usize;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<usize> {
+            self.1 += 1;
+            let polls = self.1;
+            Pin::new(&mut self.0).poll(cx).map(|_| polls)
+        }
+    }
+
+    // One unit more than three full budgets, the last one takes a fourth poll.
+    let work = Box::pin(async {
+        for _ in 0..3 * POLL_BUDGET + 1 {
+            task::consume_budget().await;
+        }
+    });
+    let child = task::spawner().spawn(Task::new(CountPolls(work, 0)));
+    assert_eq!(child.await, Ok(4));
+}
+
+async fn wakeups_are_deduplicated() {
+    static TASK_POLLS: AtomicUsize = AtomicUsize::new(0);
+    static RELEASE: Notify = Notify::new();
+
+    /// Wakes itself many times on its first poll and completes on the next.
+    struct WakeMany(bool);
+
+    impl Future for WakeMany {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            for _ in 0..1000 {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Counts every poll of the task in `TASK_POLLS`.
+    struct Counted<F>(F);
+
+    impl<F: Future + Unpin> Future for Counted<F> {
+        type Output = F::Output;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+            TASK_POLLS.fetch_add(1, Ordering::Relaxed);
+            Pin::new(&mut self.0).poll(cx)
+        }
+    }
+
+    // Parked on `RELEASE` after the second poll, so every duplicate wakeup
+    // left in a run queue would show up as another poll.
+    let work = Box::pin(async {
+        WakeMany(false).await;
+        RELEASE.notified().await;
+    });
+    let child = task::spawner().spawn(Task::new(Counted(work)));
+
+    while TASK_POLLS.load(Ordering::Relaxed) < 2 {
+        task::yield_now().await;
+    }
+    for _ in 0..100 {
+        task::yield_now().await;
+    }
+    assert_eq!(TASK_POLLS.load(Ordering::Relaxed), 2);
+
+    let tasks = task::tasks();
+    let info = tasks
+        .iter()
+        .find(|info| info.id == child.id())
+        .expect("Parked task not listed");
+    assert_eq!(info.polls, 2);
+
+    RELEASE.notify_one();
+    assert_eq!(child.await, Ok(()));
+    assert_eq!(TASK_POLLS.load(Ordering::Relaxed), 3);
+}
+
+async fn thousands_of_tasks_complete() {
+    static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+    let spawner = task::spawner();
+    let children: Vec<_> = (0..MANY_TASKS)
+        .map(|i| {
+            spawner.spawn(Task::new(async move {
+                for _ in 0..3 {
+                    task::yield_now().await;
+                }
+                COMPLETED.fetch_add(1, Ordering::Relaxed);
+                i
+            }))
+        })
+        .collect();
+
+    for (i, child) in children.into_iter().enumerate() {
+        assert_eq!(child.await, Ok(i));
+    }
+    assert_eq!(COMPLETED.load(Ordering::Relaxed), MANY_TASKS);
+}
+
+async fn live_tasks_are_listed() {
+    let child = task::spawner().spawn(
+        Task::new(core::future::pending::<()>())
+            .with_name("idle")
+            .with_priority(Priority::Background),
+    );
+    task::yield_now().await;
+
+    let tasks = task::tasks();
+    let info = tasks
+        .iter()
+        .find(|info| info.id == child.id())
+        .expect("Spawned task not listed");
+    assert_eq!(info.name, Some("idle"));
+    assert_eq!(info.priority, Priority::Background);
+
+    child.abort();
+    assert_eq!(child.await, Err(JoinError::Cancelled));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitStatus::Failure);
+    titan_os::hlt_loop();
+}
@@ -0,0 +1,222 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(titan_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use titan_os::{
+    allocator::{self, slab::SlabCache},
+    memory::{self, BuddyFrameAllocator, DmaBuffer, DmaRegion, FRAME_ALLOCATOR, MAPPER},
+};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags,
+    },
+    PhysAddr, VirtAddr,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Initialization failed");
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn frame_is_reused() {
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let free = frame_allocator.free_frames();
+
+    let frame = frame_allocator.allocate_frame().unwrap();
+    assert_eq!(frame_allocator.free_frames(), free - 1);
+
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    assert_eq!(frame_allocator.free_frames(), free);
+    assert_eq!(frame_allocator.allocate_frame(), Some(frame));
+}
+
+#[test_case]
+fn contiguous_allocation() {
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let used = frame_allocator.used_frames();
+
+    let range = frame_allocator.allocate_contiguous(16, 4).unwrap();
+    assert_eq!(range.count(), 16);
+    assert_eq!(
+        range.start.start_address().as_u64() % (4 * memory::FRAME_SIZE),
+        0
+    );
+    assert_eq!(frame_allocator.used_frames(), used + 16);
+
+    unsafe { frame_allocator.deallocate_contiguous(range) };
+    assert_eq!(frame_allocator.used_frames(), used);
+}
+
+#[test_case]
+fn low_memory_is_kept_for_real_mode() {
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let limit = PhysAddr::new(0x10_0000);
+
+    let frame = frame_allocator.allocate_frame().unwrap();
+    assert!(frame.start_address() >= limit);
+    let low = frame_allocator.allocate_frame_below(limit).unwrap();
+    assert!(low.start_address() < limit);
+    assert_ne!(low.start_address().as_u64(), 0);
+
+    unsafe {
+        frame_allocator.deallocate_frame(low);
+        frame_allocator.deallocate_frame(frame);
+    }
+}
+
+#[test_case]
+fn buddy_frame_allocator_merges() {
+    let frames = FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_contiguous(64, 64)
+        .unwrap();
+    let offset = OffsetPageTable::phys_offset(&MAPPER.get().unwrap().lock());
+
+    let mut buddy = BuddyFrameAllocator::new(offset);
+    unsafe { buddy.add_frames(frames) };
+    assert_eq!(buddy.free_frames(), 64);
+
+    let range = buddy.allocate_contiguous(3, 1).unwrap();
+    let frame = buddy.allocate_frame().unwrap();
+    assert_eq!(buddy.used_frames(), 4);
+
+    unsafe {
+        buddy.deallocate_contiguous(range);
+        buddy.deallocate_frame(frame);
+    }
+    assert_eq!(buddy.free_frames(), 64);
+    assert_eq!(
+        buddy.allocate_contiguous(64, 1).map(|range| range.start),
+        Some(frames.start)
+    );
+
+    unsafe {
+        FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .deallocate_contiguous(frames)
+    };
+}
+
+#[test_case]
+fn buddy_frame_allocator_backs_page_tables() {
+    let frames = FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_contiguous(16, 16)
+        .unwrap();
+    let mut mapper = MAPPER.get().unwrap().lock();
+
+    let mut buddy = BuddyFrameAllocator::new(OffsetPageTable::phys_offset(&mapper));
+    unsafe { buddy.add_frames(frames) };
+
+    // Away from the heap and kernel space, so the page tables come from `buddy` too.
+    let page = Page::containing_address(VirtAddr::new(0x_6666_0000_0000));
+    let frame = buddy.allocate_frame().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    unsafe {
+        mapper
+            .map_to(page, frame, flags, &mut buddy)
+            .unwrap()
+            .flush()
+    };
+
+    let value = page.start_address().as_mut_ptr::<u64>();
+    unsafe {
+        value.write_volatile(42);
+        assert_eq!(value.read_volatile(), 42);
+    }
+
+    let (unmapped, flush) = mapper.unmap(page).unwrap();
+    flush.flush();
+    assert_eq!(unmapped, frame);
+    unsafe { buddy.deallocate_frame(unmapped) };
+    drop(mapper);
+
+    // The page tables stay in use, every other frame goes back.
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    while let Some(frame) = buddy.allocate_frame() {
+        assert!(frames.start <= frame && frame < frames.end);
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+}
+
+#[test_case]
+fn dma_buffer_is_zeroed_and_freed() {
+    const SIZE: usize = 8192;
+
+    // The first mapping may allocate page tables that are never returned.
+    drop(DmaBuffer::new(0u64).unwrap());
+    let free = FRAME_ALLOCATOR.get().unwrap().lock().free_frames();
+
+    // Dirty the frames so the next region can only be zero if it was cleared.
+    let dirty = DmaRegion::new(SIZE).unwrap();
+    unsafe { core::ptr::write_bytes(dirty.as_mut_ptr(), 0xff, SIZE) };
+    let phys_addr = dirty.phys_addr();
+    drop(dirty);
+
+    let region = DmaRegion::new(SIZE).unwrap();
+    assert_eq!(region.phys_addr(), phys_addr);
+    let bytes = unsafe { core::slice::from_raw_parts(region.as_mut_ptr(), SIZE) };
+    assert!(bytes.iter().all(|&byte| byte == 0));
+    drop(region);
+
+    let buffer = DmaBuffer::<[u64]>::new_slice(SIZE / 8).unwrap();
+    assert!(buffer.phys_addr().is_aligned(memory::FRAME_SIZE));
+    drop(buffer);
+    assert!(DmaBuffer::<[u64]>::new_slice(usize::MAX / 4).is_err());
+
+    assert_eq!(FRAME_ALLOCATOR.get().unwrap().lock().free_frames(), free);
+}
+
+#[test_case]
+fn slab_cache_reuses_and_reclaims() {
+    let cache = SlabCache::create("test-objects", || [7u64; 12]);
+
+    let first = cache.alloc().unwrap();
+    let address = &*first as *const _ as usize;
+    assert_eq!(*first, [7; 12]);
+    drop(first);
+
+    let second = cache.alloc_with([1; 12]).unwrap();
+    assert_eq!(&*second as *const _ as usize, address);
+    assert_eq!(second[0], 1);
+
+    let objects_per_slab = cache.stats().objects_per_slab;
+    let others: Vec<_> = (0..objects_per_slab)
+        .map(|_| cache.alloc().unwrap())
+        .collect();
+    assert_eq!(cache.stats().slabs, 2);
+    assert_eq!(cache.stats().active_objects, objects_per_slab + 1);
+
+    let free = FRAME_ALLOCATOR.get().unwrap().lock().free_frames();
+    drop(others);
+    assert_eq!(cache.shrink(), 1);
+    assert!(FRAME_ALLOCATOR.get().unwrap().lock().free_frames() > free);
+    assert_eq!(cache.stats().slabs, 1);
+    assert!(allocator::slab::caches()
+        .iter()
+        .any(|stats| stats.name == "test-objects" && stats.active_objects == 1));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    titan_os::test_panic_handler(info)
+}
@@ -6,11 +6,16 @@
 
 extern crate alloc;
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use bootloader::{entry_point, BootInfo};
-use core::panic::PanicInfo;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    panic::PanicInfo,
+};
 use titan_os::{
-    allocator::{self, HEAP_SIZE},
+    allocator::{
+        self, buddy::BuddyAllocator, fixed_size::FixedSizeBlockAllocator, Locked, HEAP_SIZE,
+    },
     memory,
 };
 
@@ -18,8 +23,8 @@ entry_point!(main);
 
 fn main(boot_info: &'static BootInfo) -> ! {
     titan_os::init();
-    let (mut mapper, frame_allocator) = unsafe { memory::init(&boot_info) };
-    allocator::init_heap(&mut mapper, frame_allocator).expect("Initialization failed");
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Initialization failed");
     test_main();
     loop {}
 }
@@ -50,7 +55,7 @@ fn many_boxes() {
     }
 }
 
-#[cfg(not(bump_allocator))]
+#[cfg(not(feature = "alloc-bump"))]
 #[test_case]
 fn many_boxes_long_lived() {
     let long = Box::new(1);
@@ -63,6 +68,56 @@ fn many_boxes_long_lived() {
     assert_eq!(*long, 1);
 }
 
+#[cfg(any(feature = "alloc-fixed-size", feature = "alloc-buddy"))]
+#[test_case]
+fn heap_grows_beyond_initial_size() {
+    let large = vec![1u8; HEAP_SIZE * 4];
+    assert!(large.iter().all(|&b| b == 1));
+}
+
+#[test_case]
+fn fixed_size_allocator_over_buddy() {
+    const REGION_SIZE: usize = 16 * 1024;
+    let region = Layout::from_size_align(REGION_SIZE, REGION_SIZE).unwrap();
+    let start = unsafe { alloc::alloc::alloc(region) };
+    assert!(!start.is_null());
+
+    let allocator = Locked::new(FixedSizeBlockAllocator::with_fallback(BuddyAllocator::new()));
+    unsafe { allocator.lock().init(start as usize, REGION_SIZE) };
+
+    let small = Layout::new::<u64>();
+    let large = Layout::from_size_align(4096, 4096).unwrap();
+    unsafe {
+        let block = allocator.alloc(small);
+        let page = allocator.alloc(large);
+        assert!((start..start.add(REGION_SIZE)).contains(&block));
+        assert!((start..start.add(REGION_SIZE)).contains(&page));
+
+        // Blocks go back to their list, larger sizes to the buddy allocator.
+        allocator.dealloc(block, small);
+        allocator.dealloc(page, large);
+        assert_eq!(allocator.alloc(small), block);
+        assert_eq!(allocator.alloc(large), page);
+        allocator.dealloc(page, large);
+        allocator.dealloc(block, small);
+
+        alloc::alloc::dealloc(start, region);
+    }
+}
+
+#[test_case]
+fn stats_track_live_allocations() {
+    let before = allocator::stats();
+    let value = Box::new([0u8; 64]);
+
+    let during = allocator::stats();
+    assert_eq!(during.live_allocations, before.live_allocations + 1);
+    assert!(during.peak_usage >= during.in_use());
+
+    drop(value);
+    assert_eq!(allocator::stats().live_allocations, before.live_allocations);
+}
+
 #[panic_handler]
 fn panic(panic_info: &PanicInfo) -> ! {
     titan_os::test_panic_handler(panic_info)
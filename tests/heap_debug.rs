@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use titan_os::{allocator, exit_qemu, memory, serial_print, serial_println, QemuExitStatus};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Initialization failed");
+
+    serial_print!("heap_debug::double_free...\t");
+    unsafe {
+        let layout = Layout::new::<u64>();
+        let ptr = alloc(layout);
+        // More frees than the quarantine holds push `ptr` back to the heap first.
+        let others = [(); 64].map(|_| alloc(layout));
+        dealloc(ptr, layout);
+        for other in others {
+            dealloc(other, layout);
+        }
+        dealloc(ptr, layout);
+    }
+
+    serial_println!("[double free not detected]");
+    exit_qemu(QemuExitStatus::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[OK]");
+    exit_qemu(QemuExitStatus::Success);
+    loop {}
+}
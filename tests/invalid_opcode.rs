@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, fmt::Write, panic::PanicInfo};
+use titan_os::{exit_qemu, serial_print, serial_println, QemuExitStatus};
+
+const EXPECTED: &str = "EXCEPTION: INVALID OPCODE";
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("invalid_opcode::invalid_opcode...\t");
+    titan_os::init();
+
+    unsafe { asm!("ud2") };
+
+    serial_println!("[invalid opcode not reported]");
+    exit_qemu(QemuExitStatus::Failure);
+    loop {}
+}
+
+/// Keeps the start of a panic report, there is no heap to format it into.
+struct Message {
+    bytes: [u8; 256],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut message = Message {
+        bytes: [0; 256],
+        len: 0,
+    };
+    let _ = write!(message, "{}", info);
+
+    // Any other panic, a #GP or #DF report included, is a failure.
+    let reported = message.bytes[..message.len]
+        .windows(EXPECTED.len())
+        .any(|window| window == EXPECTED.as_bytes());
+    if !reported {
+        titan_os::test_panic_handler(info);
+    }
+
+    serial_println!("[OK]");
+    exit_qemu(QemuExitStatus::Success);
+    loop {}
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(titan_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use titan_os::{acpi, allocator, interrupts::apic, memory, smp, time};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Heap initialization failed");
+    acpi::init().expect("No ACPI tables");
+    apic::init().expect("APIC initialization failed");
+    time::init_clock_source();
+    smp::init().expect("SMP initialization failed");
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn application_processor_is_online() {
+    // The tests run with `-smp 2`.
+    assert_eq!(smp::online_cpus(), 2);
+}
+
+#[test_case]
+fn bootstrap_processor_is_cpu_zero() {
+    let cpu = smp::percpu::current().expect("No per-CPU data");
+    assert_eq!(cpu.id(), 0);
+    assert_eq!(Some(cpu.apic_id()), apic::local_apic_id());
+    assert_eq!(smp::cpu_id(), 0);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    titan_os::test_panic_handler(info)
+}
@@ -0,0 +1,268 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use titan_os::{
+    acpi, allocator, exit_qemu,
+    interrupts::apic,
+    memory, serial_print, serial_println, smp,
+    task::{
+        self,
+        executor::Executor,
+        sync::{mpsc, oneshot, Mutex, Notify, RwLock, Semaphore},
+        Task,
+    },
+    time, QemuExitStatus,
+};
+
+/// Tasks contending for each primitive, enough to keep every CPU busy.
+const TASKS: usize = 8;
+const ROUNDS: usize = 100;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    titan_os::init();
+    unsafe { memory::init(boot_info) };
+    allocator::init_heap().expect("Heap initialization failed");
+    acpi::init().expect("No ACPI tables");
+    apic::init().expect("APIC initialization failed");
+    time::init_clock_source();
+    smp::init().expect("SMP initialization failed");
+
+    let executor = Executor::new();
+    executor.spawn(Task::new(run_tests())).detach();
+    executor.run();
+}
+
+async fn run_tests() {
+    serial_print!("sync::mutex_is_exclusive...\t");
+    mutex_is_exclusive().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::rwlock_shares_reads...\t");
+    rwlock_shares_reads().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::waiting_writer_holds_back_readers...\t");
+    waiting_writer_holds_back_readers().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::semaphore_limits_holders...\t");
+    semaphore_limits_holders().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::notify_wakes_waiters...\t");
+    notify_wakes_waiters().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::oneshot_delivers_value...\t");
+    oneshot_delivers_value().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::bounded_channel_waits_for_room...\t");
+    bounded_channel_waits_for_room().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::unbounded_channel_ends_with_senders...\t");
+    unbounded_channel_ends_with_senders().await;
+    serial_println!("[OK]");
+
+    serial_print!("sync::send_fails_without_receiver...\t");
+    send_fails_without_receiver().await;
+    serial_println!("[OK]");
+
+    exit_qemu(QemuExitStatus::Success);
+}
+
+async fn mutex_is_exclusive() {
+    let counter = Arc::new(Mutex::new(0));
+    let children: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let counter = counter.clone();
+            task::spawner().spawn(Task::new(async move {
+                for _ in 0..ROUNDS {
+                    let mut guard = counter.lock().await;
+                    let value = *guard;
+                    // Held across a yield, another task must not get in.
+                    task::yield_now().await;
+                    *guard = value + 1;
+                }
+            }))
+        })
+        .collect();
+
+    for child in children {
+        child.await.unwrap();
+    }
+    assert_eq!(*counter.lock().await, TASKS * ROUNDS);
+}
+
+async fn rwlock_shares_reads() {
+    let lock = RwLock::new(1);
+    let first = lock.read().await;
+    let second = lock.read().await;
+    assert_eq!(*first + *second, 2);
+    assert!(lock.try_write().is_none());
+
+    drop((first, second));
+    *lock.write().await += 1;
+    assert!(lock.try_read().is_some());
+    assert_eq!(*lock.read().await, 2);
+}
+
+async fn waiting_writer_holds_back_readers() {
+    let lock = Arc::new(RwLock::new(0));
+    let reader = lock.read().await;
+
+    let writer = {
+        let lock = lock.clone();
+        task::spawner().spawn(Task::new(async move {
+            *lock.write().await = 1;
+        }))
+    };
+    while lock.try_read().is_some() {
+        task::yield_now().await;
+    }
+
+    drop(reader);
+    writer.await.unwrap();
+    assert_eq!(*lock.read().await, 1);
+}
+
+async fn semaphore_limits_holders() {
+    const PERMITS: usize = 3;
+    static HOLDERS: AtomicUsize = AtomicUsize::new(0);
+
+    let semaphore = Arc::new(Semaphore::new(PERMITS));
+    let children: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let semaphore = semaphore.clone();
+            task::spawner().spawn(Task::new(async move {
+                for _ in 0..ROUNDS {
+                    let _permit = semaphore.acquire().await.unwrap();
+                    assert!(HOLDERS.fetch_add(1, Ordering::SeqCst) < PERMITS);
+                    task::yield_now().await;
+                    HOLDERS.fetch_sub(1, Ordering::SeqCst);
+                }
+            }))
+        })
+        .collect();
+
+    for child in children {
+        child.await.unwrap();
+    }
+    assert_eq!(semaphore.available_permits(), PERMITS);
+}
+
+async fn notify_wakes_waiters() {
+    let notify = Arc::new(Notify::new());
+
+    // Kept for the next waiter when nobody waits yet.
+    notify.notify_one();
+    notify.notified().await;
+
+    let waiters: Vec<_> = (0..TASKS)
+        .map(|_| {
+            let notify = notify.clone();
+            task::spawner().spawn(Task::new(async move { notify.notified().await }))
+        })
+        .collect();
+    // Waiters that were not polled yet miss a round, so it is repeated.
+    while !waiters.iter().all(|waiter| waiter.is_finished()) {
+        notify.notify_waiters();
+        task::yield_now().await;
+    }
+    for waiter in waiters {
+        waiter.await.unwrap();
+    }
+}
+
+async fn oneshot_delivers_value() {
+    let (sender, receiver) = oneshot::channel();
+    task::spawner()
+        .spawn(Task::new(async move { sender.send(42).unwrap() }))
+        .detach();
+    assert_eq!(receiver.await, Ok(42));
+
+    let (sender, receiver) = oneshot::channel::<()>();
+    drop(sender);
+    assert_eq!(receiver.await, Err(oneshot::RecvError));
+}
+
+async fn bounded_channel_waits_for_room() {
+    let (sender, mut receiver) = mpsc::channel(2);
+    sender.try_send(0).unwrap();
+    sender.try_send(1).unwrap();
+    assert!(matches!(
+        sender.try_send(2),
+        Err(mpsc::TrySendError::Full(2))
+    ));
+
+    let producer = task::spawner().spawn(Task::new(async move {
+        for value in 2..ROUNDS {
+            sender.send(value).await.unwrap();
+        }
+    }));
+    for expected in 0..ROUNDS {
+        assert_eq!(receiver.recv().await, Some(expected));
+    }
+    producer.await.unwrap();
+    assert_eq!(receiver.recv().await, None);
+}
+
+async fn unbounded_channel_ends_with_senders() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    for _ in 0..TASKS {
+        let sender = sender.clone();
+        task::spawner()
+            .spawn(Task::new(async move {
+                for value in 0..ROUNDS {
+                    sender.send(value).unwrap();
+                    task::yield_now().await;
+                }
+            }))
+            .detach();
+    }
+    drop(sender);
+
+    let mut received = 0;
+    while receiver.recv().await.is_some() {
+        received += 1;
+    }
+    assert_eq!(received, TASKS * ROUNDS);
+}
+
+async fn send_fails_without_receiver() {
+    let (sender, receiver) = mpsc::channel(1);
+    sender.try_send(0).unwrap();
+
+    let blocked = {
+        let sender = sender.clone();
+        task::spawner().spawn(Task::new(async move { sender.send(1).await }))
+    };
+    task::yield_now().await;
+    drop(receiver);
+
+    assert_eq!(blocked.await.unwrap(), Err(mpsc::SendError(1)));
+    assert!(sender.is_closed());
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    drop(receiver);
+    assert_eq!(sender.send(2), Err(mpsc::SendError(2)));
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitStatus::Failure);
+    titan_os::hlt_loop();
+}